@@ -0,0 +1,4 @@
+// Migrations are embedded into the binary by `sqlx::migrate!`, so rebuild whenever they change.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
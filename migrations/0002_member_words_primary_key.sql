@@ -0,0 +1,18 @@
+-- add_member_words relies on ON CONFLICT (word_id, member_id, next_word_id), which needs a primary key.
+-- Deployments created from the old schema may hold duplicate rows, so merge them before adding the key.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conrelid = 'member_words'::regclass AND contype = 'p'
+    ) THEN
+        CREATE TEMPORARY TABLE merged_member_words AS
+            SELECT word_id, member_id, next_word_id, sum(frequency)::BIGINT AS frequency
+            FROM member_words
+            GROUP BY word_id, member_id, next_word_id;
+        DELETE FROM member_words;
+        INSERT INTO member_words (word_id, member_id, next_word_id, frequency)
+            SELECT word_id, member_id, next_word_id, frequency FROM merged_member_words;
+        DROP TABLE merged_member_words;
+        ALTER TABLE member_words ADD PRIMARY KEY (word_id, member_id, next_word_id);
+    END IF;
+END $$;
@@ -0,0 +1,7 @@
+-- Records how far back the `learn` command has read a channel's history, so an interrupted backfill can resume.
+CREATE TABLE IF NOT EXISTS channel_backfills (
+    channel_id BIGINT NOT NULL REFERENCES channels (channel_id) ON DELETE CASCADE,
+    oldest_message_id BIGINT,
+    complete BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (channel_id)
+);
@@ -0,0 +1,14 @@
+-- Per-server settings. markov_order is the number of words of context used when generating text.
+CREATE TABLE IF NOT EXISTS server_settings (
+    server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
+    markov_order SMALLINT NOT NULL DEFAULT 2 CHECK (markov_order BETWEEN 1 AND 3),
+    PRIMARY KEY (server_id)
+);
+-- Transitions out of contexts of two or more words. Single word transitions stay in member_words.
+CREATE TABLE IF NOT EXISTS member_ngrams (
+    member_id INTEGER NOT NULL REFERENCES members (member_id) ON DELETE CASCADE,
+    context INTEGER[] NOT NULL,
+    next_word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
+    frequency BIGINT NOT NULL,
+    PRIMARY KEY (member_id, context, next_word_id)
+);
@@ -0,0 +1,8 @@
+-- Transitions learned in each channel, for generating text that sounds like a channel or a whole server. Contexts of every order are stored here, including single words.
+CREATE TABLE IF NOT EXISTS channel_ngrams (
+    channel_id BIGINT NOT NULL REFERENCES channels (channel_id) ON DELETE CASCADE,
+    context INTEGER[] NOT NULL,
+    next_word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
+    frequency BIGINT NOT NULL,
+    PRIMARY KEY (channel_id, context, next_word_id)
+);
@@ -0,0 +1,4 @@
+-- Who the bot talks like when it is mentioned or replied to. reply_member_id is only used when reply_persona is 'member'.
+ALTER TABLE server_settings
+    ADD COLUMN IF NOT EXISTS reply_persona TEXT NOT NULL DEFAULT 'channel' CHECK (reply_persona IN ('member', 'channel', 'server')),
+    ADD COLUMN IF NOT EXISTS reply_member_id INTEGER REFERENCES members (member_id) ON DELETE SET NULL;
@@ -0,0 +1,8 @@
+-- Channels where the bot joins the conversation on its own. It chimes in after every_n_messages messages, or with the given probability for each message, but never more often than once per cooldown.
+CREATE TABLE IF NOT EXISTS channel_chime_settings (
+    channel_id BIGINT NOT NULL REFERENCES channels (channel_id) ON DELETE CASCADE,
+    every_n_messages INTEGER CHECK (every_n_messages > 0),
+    probability DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (probability BETWEEN 0 AND 1),
+    cooldown_seconds INTEGER NOT NULL CHECK (cooldown_seconds >= 0),
+    PRIMARY KEY (channel_id)
+);
@@ -0,0 +1,4 @@
+-- Sentences can grow backwards from a keyword, which looks transitions up by the word they lead to.
+CREATE INDEX IF NOT EXISTS member_words_next_word_idx ON member_words (member_id, next_word_id);
+CREATE INDEX IF NOT EXISTS member_ngrams_next_word_idx ON member_ngrams (member_id, next_word_id);
+CREATE INDEX IF NOT EXISTS channel_ngrams_next_word_idx ON channel_ngrams (channel_id, next_word_id);
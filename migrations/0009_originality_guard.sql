@@ -0,0 +1,9 @@
+-- Hashes of runs of words from every learned message in a server, so generated text that copies a real message can be caught without keeping the messages themselves.
+-- Only messages learned from after this migration are covered.
+CREATE TABLE IF NOT EXISTS message_shingles (
+    server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
+    shingle_hash BIGINT NOT NULL,
+    PRIMARY KEY (server_id, shingle_hash)
+);
+-- The largest share of a generated sentence that may match learned messages before it is generated again. 1 turns the guard off.
+ALTER TABLE server_settings ADD COLUMN IF NOT EXISTS originality_threshold DOUBLE PRECISION NOT NULL DEFAULT 0.5 CHECK (originality_threshold BETWEEN 0 AND 1);
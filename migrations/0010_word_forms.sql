@@ -0,0 +1,9 @@
+-- How often each word was typed each way, at the start of a sentence and everywhere else, so generated text can be typed the way it usually is. words.word is the lowercased key that transitions are matched by.
+-- Words learned before this migration were stored lowercased without punctuation, and are typed as their key until they are learned again.
+CREATE TABLE IF NOT EXISTS word_forms (
+    word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
+    form TEXT NOT NULL,
+    sentence_start BOOLEAN NOT NULL,
+    frequency BIGINT NOT NULL,
+    PRIMARY KEY (word_id, form, sentence_start)
+);
@@ -0,0 +1,7 @@
+-- The webhook the bot made in a channel to post generated text under a member's name and avatar. One per channel, reused until the channel is untracked.
+CREATE TABLE IF NOT EXISTS channel_webhooks (
+    channel_id BIGINT NOT NULL REFERENCES channels (channel_id) ON DELETE CASCADE,
+    webhook_id BIGINT NOT NULL,
+    token TEXT NOT NULL,
+    PRIMARY KEY (channel_id)
+);
@@ -0,0 +1,8 @@
+-- The leaderboard of the "Who said it?" game. A player wins a round by being the first to guess which member the generated sentence imitates.
+CREATE TABLE IF NOT EXISTS game_scores (
+    server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL,
+    wins INTEGER NOT NULL CHECK (wins >= 0),
+    PRIMARY KEY (server_id, user_id)
+);
+CREATE INDEX IF NOT EXISTS game_scores_ranking ON game_scores (server_id, wins DESC);
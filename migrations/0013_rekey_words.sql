@@ -0,0 +1,16 @@
+-- Word keys are now compatibility normalized and case folded, so `straße` is stored as `strasse` and `ﬁne` as `fine`, and the emoji presentation selector is dropped. Before, they were only lowercased.
+-- Words learned before are given their new key, so seeds still find them. Their word_id stays the same, so their transitions carry over.
+-- `lower()` only folds the letters the database's LC_CTYPE knows about, and under the common `C` locale that is only ASCII. Old keys were already lowercased when they were learned, so this only misses non-ASCII uppercase letters that NFKC normalization produces, like `Ɛ` from `ℇ`. Those words keep a key `textprocessing::normalize` never produces, and can't be used as seeds until they are learned again.
+-- A word whose new key is already taken by a word learned since keeps its old key, since merging the two would mean merging their transitions. It can't be used as a seed anymore, but still takes part in generated text.
+UPDATE words
+SET word = rekeyed.new_key
+FROM (
+    SELECT DISTINCT ON (new_key) word_id, new_key
+    FROM (
+        SELECT word_id, word, replace(replace(lower(replace(normalize(word, NFKC), U&'\FE0F', '')), 'ß', 'ss'), 'ς', 'σ') AS new_key
+        FROM words
+    ) AS keys
+    WHERE new_key <> word AND NOT EXISTS (SELECT 1 FROM words AS taken WHERE taken.word = keys.new_key)
+    ORDER BY new_key, word_id
+) AS rekeyed
+WHERE words.word_id = rekeyed.word_id;
@@ -1,3 +1,7 @@
+pub mod chime;
 pub mod commands;
+pub mod data;
 pub mod database;
+pub mod game;
 pub mod textgen;
+pub mod webhook;
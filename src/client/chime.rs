@@ -0,0 +1,141 @@
+use rand::Rng;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::client::database::entities::ChimeSettings;
+use crate::client::textgen::{markov, textprocessing};
+
+/** The cooldown used when a channel is set up without one. */
+pub const DEFAULT_CHIME_COOLDOWN_SECONDS: u64 = 300;
+/** The shortest cooldown that can be set, so the bot never spams a channel. */
+pub const MIN_CHIME_COOLDOWN_SECONDS: u64 = 30;
+/** How many of the latest messages in a channel are used to seed what the bot chimes in with. */
+pub const RECENT_CONTEXT_MESSAGES: usize = 5;
+
+/** What the bot remembers about a channel between messages to decide when to chime in. Kept in memory only, so it starts over when the bot restarts. */
+#[derive(Debug, Default)]
+pub struct ChimeState {
+    messages_since_chime: u32,
+    last_chime: Option<Instant>,
+    recent_messages: VecDeque<String>,
+}
+
+impl ChimeState {
+    /** Records a message sent in the channel and decides whether the bot should chime in after it. */
+    pub fn record_message<R: Rng>(
+        &mut self,
+        content: &str,
+        settings: &ChimeSettings,
+        now: Instant,
+        rng: &mut R,
+    ) -> bool {
+        self.recent_messages.push_back(content.to_owned());
+        if self.recent_messages.len() > RECENT_CONTEXT_MESSAGES {
+            self.recent_messages.pop_front();
+        }
+        self.messages_since_chime += 1;
+        if let Some(last_chime) = self.last_chime {
+            if now.duration_since(last_chime) < Duration::from_secs(settings.cooldown_seconds) {
+                return false;
+            }
+        }
+        let chime = settings
+            .every_n_messages
+            .is_some_and(|every_n_messages| self.messages_since_chime >= every_n_messages)
+            || rng.gen::<f64>() < settings.probability;
+        if chime {
+            self.messages_since_chime = 0;
+            self.last_chime = Some(now);
+        }
+        chime
+    }
+
+    /** Returns the content words of the latest messages, newest message first, to seed what the bot chimes in with. */
+    pub fn seeds(&self) -> Vec<String> {
+        let mut seeds: Vec<String> = vec![];
+        for content in self.recent_messages.iter().rev() {
+            for word in
+                textprocessing::content_words(&markov::filter_string_pre_processing(content))
+            {
+                if !seeds.contains(&word) {
+                    seeds.push(word);
+                }
+            }
+        }
+        seeds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    fn settings(every_n_messages: Option<u32>, probability: f64) -> ChimeSettings {
+        ChimeSettings {
+            every_n_messages,
+            probability,
+            cooldown_seconds: 60,
+        }
+    }
+
+    #[test]
+    fn test_chime_every_n_messages() {
+        let mut state = ChimeState::default();
+        let settings = settings(Some(3), 0.0);
+        let mut rng = StepRng::new(0, 0);
+        let now = Instant::now();
+        let chimes: Vec<bool> = (0..3)
+            .map(|_| state.record_message("hello", &settings, now, &mut rng))
+            .collect();
+        assert_eq!(vec![false, false, true], chimes);
+    }
+
+    #[test]
+    fn test_chime_probability() {
+        let mut state = ChimeState::default();
+        // StepRng at 0 always draws 0.0, which is below any probability above 0
+        let mut rng = StepRng::new(0, 0);
+        assert!(state.record_message("hello", &settings(None, 0.1), Instant::now(), &mut rng));
+        let mut state = ChimeState::default();
+        assert!(!state.record_message("hello", &settings(None, 0.0), Instant::now(), &mut rng));
+    }
+
+    #[test]
+    fn test_chime_cooldown() {
+        let mut state = ChimeState::default();
+        let settings = settings(Some(1), 0.0);
+        let mut rng = StepRng::new(0, 0);
+        let now = Instant::now();
+        assert!(state.record_message("hello", &settings, now, &mut rng));
+        assert!(!state.record_message("hello", &settings, now + Duration::from_secs(59), &mut rng));
+        assert!(state.record_message("hello", &settings, now + Duration::from_secs(60), &mut rng));
+    }
+
+    #[test]
+    fn test_chime_seeds_newest_first() {
+        let mut state = ChimeState::default();
+        let settings = settings(None, 0.0);
+        let mut rng = StepRng::new(0, 0);
+        let now = Instant::now();
+        for content in [
+            "old pizza",
+            "stale bread",
+            "pizza party",
+            "fresh tacos",
+            "cold soda",
+            "hot coffee",
+        ] {
+            state.record_message(content, &settings, now, &mut rng);
+        }
+        // Only the latest messages are remembered
+        assert_eq!(
+            vec![
+                "coffee", "hot", "cold", "soda", "fresh", "tacos", "pizza", "party", "stale",
+                "bread"
+            ],
+            state.seeds()
+        );
+    }
+}
@@ -1,4 +1,36 @@
-mod errors;
+use crate::client::data::Data;
+use tracing::error;
+
+pub mod game;
 pub mod general;
 pub mod owner;
-mod utils;
+pub mod utils;
+
+/** The error returned by commands. Its message is shown to the user who invoked the command. */
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+/** The context handed to every command. */
+pub type Context<'a> = poise::Context<'a, Data, Error>;
+
+/** Replies to failed commands with a message only the invoking user can see, and leaves every other framework error to poise. */
+pub async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    match error {
+        poise::FrameworkError::Command { error, ctx } => {
+            let reply = match error.downcast_ref::<sqlx::Error>() {
+                Some(why) => format!("An SQLx error occurred: {}", why),
+                None => error.to_string(),
+            };
+            if let Err(why) = ctx.send(|m| m.content(reply).ephemeral(true)).await {
+                error!(
+                    "Could not reply to the error in command `{}`: {}",
+                    ctx.command().name,
+                    why
+                );
+            }
+        }
+        error => {
+            if let Err(why) = poise::builtins::on_error(error).await {
+                error!("Error while handling error: {}", why);
+            }
+        }
+    }
+}
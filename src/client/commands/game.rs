@@ -0,0 +1,189 @@
+use futures::StreamExt;
+use rand::seq::SliceRandom;
+use serenity::model::prelude::*;
+use serenity::prelude::Mentionable;
+use std::time::Duration;
+use tracing::error;
+
+use crate::client::commands::utils;
+use crate::client::commands::{Context, Error};
+use crate::client::database::entities::{Personality, SeedPosition};
+use crate::client::database::errors::{MarkovFetchResultError, RemoveResult};
+use crate::client::game::{self, Guess, Round, Suspect};
+use crate::client::textgen::markov::DEFAULT_TEMPERATURE;
+
+/// Play a round of "Who said it?".
+///
+/// I imitate a random tracked member, and the first player to guess who it was by mentioning them or writing their name wins the round. Everyone gets one guess.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn whosaidit(
+    ctx: Context<'_>,
+    #[description = "How many seconds players have to guess, from 10 to 300"]
+    #[min = 10]
+    #[max = 300]
+    seconds: Option<u64>,
+) -> Result<(), Error> {
+    let seconds = seconds.unwrap_or(game::DEFAULT_GUESS_SECONDS);
+    if !(game::MIN_GUESS_SECONDS..=game::MAX_GUESS_SECONDS).contains(&seconds) {
+        return Err(format!(
+            "Players must have from {} to {} seconds to guess!",
+            game::MIN_GUESS_SECONDS,
+            game::MAX_GUESS_SECONDS
+        )
+        .into());
+    }
+    if !ctx.data().games.lock().await.insert(ctx.channel_id().0) {
+        return Err("A round is already going on in this channel!".into());
+    }
+    let result = play_round(ctx, Duration::from_secs(seconds)).await;
+    ctx.data().games.lock().await.remove(&ctx.channel_id().0);
+    result
+}
+
+/** Runs a round of "Who said it?" from the sentence to the reveal. */
+async fn play_round(ctx: Context<'_>, time_limit: Duration) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+    let mut member_ids = match db_int.fetch_tracked_members(guild_id.0).await? {
+        Some(member_ids) if member_ids.len() >= 2 => member_ids,
+        _ => return Err("I need to be tracking at least two members to play! Add more using the `trackmember` command.".into()),
+    };
+    member_ids.shuffle(&mut rand::thread_rng());
+    let mut imitated = None;
+    for member_id in member_ids.into_iter().take(game::MAX_GENERATION_ATTEMPTS) {
+        match ctx
+            .data()
+            .model
+            .generate(
+                &Personality::Members(vec![(member_id, 1.0)]),
+                DEFAULT_TEMPERATURE,
+                None,
+                SeedPosition::Start,
+            )
+            .await
+        {
+            Ok(sentence) => {
+                imitated = Some((member_id, sentence));
+                break;
+            }
+            Err(MarkovFetchResultError::SqlxError(why)) => return Err(why.into()),
+            // Someone who hasn't said enough yet, so try someone else
+            Err(_) => continue,
+        }
+    }
+    let (member_id, sentence) = imitated.ok_or(
+        "I couldn't come up with anything convincing for anyone. Try again once I've learned more!",
+    )?;
+    let answer = db_int
+        .fetch_member_user(member_id)
+        .await?
+        .ok_or("The member I imitated just stopped being tracked. Try again!")?;
+    let mut suspects = vec![];
+    for user_id in db_int
+        .fetch_tracked_user_members(guild_id.0)
+        .await?
+        .unwrap_or_default()
+    {
+        let names = utils::member_names(ctx.serenity_context(), guild_id, UserId(user_id)).await;
+        suspects.push(Suspect::new(user_id, &names));
+    }
+    let mut round = Round::new(answer, suspects);
+
+    ctx.say(format!(
+        "**Who said it?** You have {} seconds to guess by mentioning them or writing their name!\n> {}",
+        time_limit.as_secs(),
+        utils::sendable(ctx.serenity_context(), guild_id, &sentence).await
+    ))
+    .await?;
+    let mut messages = ctx
+        .channel_id()
+        .await_replies(ctx.serenity_context())
+        .timeout(time_limit)
+        .build();
+    let mut winner = None;
+    while let Some(message) = messages.next().await {
+        if message.author.bot {
+            continue;
+        }
+        let mentioned: Vec<u64> = message.mentions.iter().map(|user| user.id.0).collect();
+        match round.guess(message.author.id.0, &message.content, &mentioned) {
+            Guess::Correct => {
+                winner = Some(message.author.id);
+                break;
+            }
+            // Lets players know their guess counted, even though the answer is only revealed at the end
+            Guess::Wrong(_) => {
+                if let Err(why) = message.react(ctx, '❌').await {
+                    error!("Could not react to guess {}: {}", message.id, why);
+                }
+            }
+            Guess::AlreadyGuessed | Guess::Chatter => {}
+        }
+    }
+    messages.stop();
+
+    let answer = UserId(round.answer()).mention();
+    match winner {
+        Some(winner) => {
+            let wins = db_int.add_game_win(guild_id.0, winner.0).await?;
+            ctx.say(format!(
+                "{} got it, it was {}! They have won {} {} now.",
+                winner.mention(),
+                answer,
+                wins,
+                if wins == 1 { "round" } else { "rounds" }
+            ))
+            .await?;
+        }
+        None => {
+            ctx.say(format!("Time's up! It was {}.", answer)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Show who has won the most rounds of "Who said it?" in this server.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn leaderboard(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let scores = ctx
+        .data()
+        .db
+        .fetch_leaderboard(guild_id.0, game::LEADERBOARD_SIZE)
+        .await?;
+    if scores.is_empty() {
+        ctx.say("Nobody has won a round of `whosaidit` here yet!")
+            .await?;
+        return Ok(());
+    }
+    let mut message = serenity::utils::MessageBuilder::new();
+    message.push_bold_line("Who said it? leaderboard:");
+    for (place, score) in scores.into_iter().enumerate() {
+        message.push_line(format!(
+            "{}. {}: {} {}",
+            place + 1,
+            UserId(score.user_id).mention(),
+            score.wins,
+            if score.wins == 1 { "win" } else { "wins" }
+        ));
+    }
+    ctx.say(message.build()).await?;
+    Ok(())
+}
+
+/// Wipe the "Who said it?" leaderboard of this server.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn resetleaderboard(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    match ctx.data().db.reset_leaderboard(guild_id.0).await? {
+        RemoveResult::Removed => ctx.say("The leaderboard was wiped!").await?,
+        RemoveResult::NotPresent => ctx.say("The leaderboard is already empty!").await?,
+    };
+    Ok(())
+}
@@ -1,451 +1,1008 @@
-use rand::Rng;
-use serenity::framework::standard::macros::command;
-use serenity::framework::standard::{Args, CommandResult};
+use itertools::Itertools;
 use serenity::model::prelude::*;
-use serenity::prelude::*;
-use serenity::utils::ArgumentConvert;
+use serenity::prelude::Mentionable;
+use std::collections::HashMap;
 
+use crate::client::chime;
 use crate::client::commands::utils;
-use crate::client::database::errors::{InsertResult, MarkovFetchResultError};
-use crate::client::database::interface::DbInterface;
+use crate::client::commands::{Context, Error};
+use crate::client::database::entities::{ChimeSettings, Personality, ReplyPersona, SeedPosition};
+use crate::client::database::errors::{InsertResult, RemoveResult};
+use crate::client::textgen::markov::DEFAULT_TEMPERATURE;
+use crate::client::textgen::textprocessing::MAX_MARKOV_ORDER;
+use crate::client::webhook;
 
-const MIN_SENTENCE_LENGTH: u8 = 4;
-const MAX_SENTENCE_LENGTH: u8 = 20;
+/// The number of messages requested from discord at a time when learning from channel history. 100 is the most discord allows.
+const BACKFILL_PAGE_SIZE: u64 = 100;
+/// The options of the mimic commands that prefix commands give by name.
+const GENERATION_OPTIONS: [&str; 2] = ["temperature", "position"];
 
-#[command]
-#[description("Mimic the specified member.")]
-#[min_args(1)]
-#[max_args(1)]
-pub async fn mimic(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    // Argument parsing here
-    let member: Member;
-    if args.len() == 0 {
-        msg.reply(&ctx.http, "Please mention a member!").await;
-        return Ok(());
+/// Where a word or phrase to start from ends up in the sentence.
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum PositionChoice {
+    Start,
+    Anywhere,
+}
+
+impl From<PositionChoice> for SeedPosition {
+    fn from(position: PositionChoice) -> Self {
+        match position {
+            PositionChoice::Start => SeedPosition::Start,
+            PositionChoice::Anywhere => SeedPosition::Anywhere,
+        }
     }
-    // Fine to use unwrap here since we already checked if we have at least 1 arg
-    let possible_member = args.current().unwrap();
-    match Member::convert(ctx, msg.guild_id, Some(msg.channel_id), possible_member).await {
-        Ok(member_found) => member = member_found,
-        Err(why) => {
-            msg.reply(
-                &ctx.http,
-                format!(
-                    "The following error occurred while parsing the first argument: {}",
-                    why
-                ),
+}
+
+/// Mimic the specified member.
+///
+/// A temperature above 1 makes me more adventurous, below 1 more predictable. Give a word or phrase to hear what they'd say about it, at the start of the sentence or anywhere in it.
+///
+/// In a tracked channel I can post with their name and avatar instead, marked as coming from me.
+///
+/// With the prefix, options go by name before the word or phrase, e.g. `triple mimic @member temperature:2 position:anywhere as_member:true the cat`.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn mimic(
+    ctx: Context<'_>,
+    #[description = "The member to mimic"] member: Member,
+    #[description = "How adventurous to be, from 0.1 to 5"]
+    #[autocomplete = "utils::autocomplete_temperature"]
+    #[min = 0.1]
+    #[max = 5.0]
+    temperature: Option<f64>,
+    #[description = "Whether the sentence starts with the word or phrase, or has it anywhere"]
+    position: Option<PositionChoice>,
+    #[description = "Whether to post with their name and avatar, which only works in tracked channels"]
+    as_member: Option<bool>,
+    #[description = "A word or phrase to start from"]
+    #[rest]
+    seed: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    // Prefix commands take options by name, so the word or phrase to start from can begin with any word
+    let (temperature, position, as_member, seed) = match ctx {
+        poise::Context::Prefix(prefix) => {
+            let options = utils::PrefixOptions::parse(
+                prefix.args,
+                1,
+                &["temperature", "position", "as_member"],
+            );
+            (
+                options.get("temperature")?,
+                options.get("position")?,
+                options.get("as_member")?,
+                options.seed,
             )
-            .await;
-            return Ok(());
         }
-    }
-    // Obtain db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Db Interface is definitely here")
-        .lock()
-        .await;
+        _ => (temperature, position, as_member, seed),
+    };
+    let temperature = utils::parse_temperature(temperature)?;
+    let db_int = &ctx.data().db;
     // Check that member is stored
-    let internal_member_id: u32;
-    match db_int
-        .fetch_member(
-            msg.guild_id.expect("Should be in a guild").0,
-            member.user.id.0,
+    let internal_member_id = match db_int.fetch_member(guild_id.0, member.user.id.0).await? {
+        Some(member_found) => member_found,
+        None => return Err(format!("{} is not a member that I know about! Help me learn about them using the `trackmember` command!", member.mention()).into()),
+    };
+    let as_member = as_member.unwrap_or(false);
+    if as_member
+        && !db_int
+            .is_tracked_channel(guild_id.0, ctx.channel_id().0)
+            .await?
+    {
+        return Err(format!("I only post as members in tracked channels, and {} is not one! Use the `trackchannel` command first.", ctx.channel_id().mention()).into());
+    }
+    match ctx
+        .data()
+        .model
+        .generate(
+            &Personality::Members(vec![(internal_member_id, 1.0)]),
+            temperature,
+            seed.as_deref(),
+            position.map_or(SeedPosition::Start, SeedPosition::from),
         )
         .await
     {
-        Ok(possible_member) => {
-            if let Some(member_found) = possible_member {
-                internal_member_id = member_found;
-            } else {
-                msg.reply(&ctx.http, format!("{} is not a member that I know about! Help me learn about the musing the `trackmember` command!", member.mention())).await;
+        Ok(sentence) => {
+            let sentence = utils::sendable(ctx.serenity_context(), guild_id, &sentence).await;
+            if !as_member {
+                ctx.say(sentence).await?;
                 return Ok(());
             }
+            let http = &ctx.serenity_context().http;
+            webhook::channel_webhook(http, db_int, ctx.channel_id())
+                .await?
+                .execute(http, false, |post| {
+                    post.content(sentence)
+                        .username(webhook::impersonation_name(&member.display_name()))
+                        .avatar_url(member.face())
+                        .allowed_mentions(|mentions| mentions.empty_parse())
+                })
+                .await?;
+            // Slash commands must be answered, but the answer shouldn't take away from the post
+            if let poise::Context::Application(_) = ctx {
+                ctx.send(|reply| {
+                    reply
+                        .content(format!("Posted as {}!", member.mention()))
+                        .ephemeral(true)
+                })
+                .await?;
+            }
+            Ok(())
         }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error has occurred: {}", why))
-                .await;
-            return Ok(());
-        }
+        Err(why) => Err(utils::generation_error(why, &member.mention().to_string())),
     }
+}
 
-    // Random sentence length
-    let sentence_length = MAX_SENTENCE_LENGTH
-        * (rand::thread_rng().gen_range(MIN_SENTENCE_LENGTH..=MAX_SENTENCE_LENGTH)
-            / MAX_SENTENCE_LENGTH);
-    // Now fetch words from db
-    match db_int
-        .fetch_random_member_words_into_sentence(internal_member_id, sentence_length)
+/// Mimic a whole channel.
+///
+/// Only messages I learned from count towards a channel. With the prefix, options go by name before the word or phrase, e.g. `temperature:2 position:anywhere`.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn mimicchannel(
+    ctx: Context<'_>,
+    #[description = "The channel to mimic"]
+    #[channel_types("Text")]
+    channel: Channel,
+    #[description = "How adventurous to be, from 0.1 to 5"]
+    #[autocomplete = "utils::autocomplete_temperature"]
+    #[min = 0.1]
+    #[max = 5.0]
+    temperature: Option<f64>,
+    #[description = "Whether the sentence starts with the word or phrase, or has it anywhere"]
+    position: Option<PositionChoice>,
+    #[description = "A word or phrase to start from"]
+    #[rest]
+    seed: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    // Prefix commands take options by name, so the word or phrase to start from can begin with any word
+    let (temperature, position, seed) = match ctx {
+        poise::Context::Prefix(prefix) => {
+            let options = utils::PrefixOptions::parse(prefix.args, 1, &GENERATION_OPTIONS);
+            (
+                options.get("temperature")?,
+                options.get("position")?,
+                options.seed,
+            )
+        }
+        _ => (temperature, position, seed),
+    };
+    let temperature = utils::parse_temperature(temperature)?;
+    match ctx
+        .data()
+        .model
+        .generate(
+            &Personality::Channel(channel.id().0),
+            temperature,
+            seed.as_deref(),
+            position.map_or(SeedPosition::Start, SeedPosition::from),
+        )
         .await
     {
         Ok(sentence) => {
-            msg.reply(&ctx.http, sentence).await;
-            return Ok(());
+            ctx.say(utils::sendable(ctx.serenity_context(), guild_id, &sentence).await)
+                .await?;
+            Ok(())
         }
-        Err(ref why) => match why {
-            MarkovFetchResultError::NotEnoughWords(
-                ref desired_sentence_length,
-                ref generated_sentence_length,
-            ) => {
-                msg.reply(&ctx.http, format!("I didn't know enough words to generate the desired sentence length of {} words. (I was able to get {} words).", desired_sentence_length, generated_sentence_length)).await;
-                return Ok(());
-            }
-            MarkovFetchResultError::SqlxError(ref sqlxerror) => {
-                msg.reply(
-                    &ctx.http,
-                    format!("An SQLx error has occurred: {}", sqlxerror),
-                )
-                .await;
-                return Ok(());
-            }
-        },
-    };
+        Err(why) => Err(utils::generation_error(why, &channel.mention().to_string())),
+    }
 }
 
-#[command]
-#[description("Begin learning about a member.")]
-#[required_permissions("MANAGE_MESSAGES")]
-#[min_args(1)]
-#[max_args(1)]
-pub async fn trackmember(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    if args.len() == 0 {
-        msg.reply(&ctx.http, "Please mention a member!").await;
-        return Ok(());
-    }
-    //Argument parsing here
-    let member: Member;
-    match utils::parse_member(
-        ctx,
-        msg.guild_id.expect("Should be in a guild"),
-        args.current().unwrap(),
-    )
-    .await
-    {
-        Ok(member_found) => member = member_found,
-        Err(why) => {
-            msg.reply(&ctx.http, why).await;
-            return Ok(());
+/// Mimic the whole server.
+///
+/// Everything I learned in any channel of this server counts. With the prefix, options go by name before the word or phrase, e.g. `temperature:2 position:anywhere`.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn mimicserver(
+    ctx: Context<'_>,
+    #[description = "How adventurous to be, from 0.1 to 5"]
+    #[autocomplete = "utils::autocomplete_temperature"]
+    #[min = 0.1]
+    #[max = 5.0]
+    temperature: Option<f64>,
+    #[description = "Whether the sentence starts with the word or phrase, or has it anywhere"]
+    position: Option<PositionChoice>,
+    #[description = "A word or phrase to start from"]
+    #[rest]
+    seed: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    // Prefix commands take options by name, so the word or phrase to start from can begin with any word
+    let (temperature, position, seed) = match ctx {
+        poise::Context::Prefix(prefix) => {
+            let options = utils::PrefixOptions::parse(prefix.args, 0, &GENERATION_OPTIONS);
+            (
+                options.get("temperature")?,
+                options.get("position")?,
+                options.seed,
+            )
         }
-    }
-    // Get db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Should have DB here")
-        .lock()
-        .await;
-    match db_int
-        .add_tracked_member(
-            msg.guild_id.expect("Should be in a guild").0,
-            member.user.id.0,
+        _ => (temperature, position, seed),
+    };
+    let temperature = utils::parse_temperature(temperature)?;
+    match ctx
+        .data()
+        .model
+        .generate(
+            &Personality::Server(guild_id.0),
+            temperature,
+            seed.as_deref(),
+            position.map_or(SeedPosition::Start, SeedPosition::from),
         )
         .await
     {
-        Ok(crate::client::database::errors::InsertResult::Added) => {
-            msg.reply(
-                &ctx.http,
-                format!("Added {} to list of tracked members!", member.mention()),
-            )
-            .await;
-            return Ok(());
-        }
-        Ok(crate::client::database::errors::InsertResult::AlreadyPresent) => {
-            msg.reply(
-                &ctx.http,
-                format!("{} is already tracked!", member.mention()),
-            )
-            .await;
-            return Ok(());
-        }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error occurred: {}", why))
-                .await;
-            return Ok(());
+        Ok(sentence) => {
+            ctx.say(utils::sendable(ctx.serenity_context(), guild_id, &sentence).await)
+                .await?;
+            Ok(())
         }
+        Err(why) => Err(utils::generation_error(why, "This server")),
     }
 }
 
-#[command]
-#[description("Stop learning about a member.")]
-#[required_permissions("MANAGE_MESSAGES")]
-#[min_args(1)]
-#[max_args(1)]
-pub async fn untrackmember(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    // Argument parsing
-    if args.len() == 0 {
-        msg.reply(&ctx.http, "Please mention a member!").await;
-        return Ok(());
+/// Show how much I learned.
+///
+/// Counts what I learned from a member, or from the whole server if no member is given.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn stats(
+    ctx: Context<'_>,
+    #[description = "The member to count for"] member: Option<Member>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let (personality, name) = match member {
+        Some(member) => match ctx.data().db.fetch_member(guild_id.0, member.user.id.0).await? {
+            Some(member_id) => (
+                Personality::Members(vec![(member_id, 1.0)]),
+                member.mention().to_string(),
+            ),
+            None => return Err(format!("{} is not a member that I know about! Help me learn about them using the `trackmember` command!", member.mention()).into()),
+        },
+        None => (Personality::Server(guild_id.0), "this server".to_string()),
+    };
+    let stats = ctx.data().model.stats(&personality).await?;
+    ctx.say(format!(
+        "I learned {} messages from {}, with {} different words and {} transitions between them.",
+        stats.messages, name, stats.words, stats.transitions
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Blend several members into one sentence.
+///
+/// Weights are given in the same order as the members, e.g. `2 1` to sound twice as much like the first member.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn mashup(
+    ctx: Context<'_>,
+    #[description = "The first member to blend"] first: Member,
+    #[description = "The second member to blend"] second: Member,
+    #[description = "Another member to blend"] third: Option<Member>,
+    #[description = "Another member to blend"] fourth: Option<Member>,
+    #[description = "Another member to blend"] fifth: Option<Member>,
+    #[description = "How much each member counts, in order, e.g. \"2 1\""]
+    #[rest]
+    weights: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let members: Vec<Member> = [Some(first), Some(second), third, fourth, fifth]
+        .into_iter()
+        .flatten()
+        .collect();
+    if members.iter().map(|member| member.user.id).unique().count() < members.len() {
+        return Err("Please pick different members to blend!".into());
     }
-    let member: Member;
-    match utils::parse_member(
-        ctx,
-        msg.guild_id.expect("Should be in a guild"),
-        args.current().unwrap(),
-    )
-    .await
-    {
-        Ok(member_found) => member = member_found,
-        Err(why) => {
-            msg.reply(&ctx.http, why).await;
-            return Ok(());
+    let weights: Vec<f64> = match weights {
+        Some(weights) => {
+            let weights = weights
+                .split_whitespace()
+                .map(|weight| weight.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .ok()
+                .filter(|weights| weights.iter().all(|weight| *weight > 0.0))
+                .ok_or("The weights must be positive numbers!")?;
+            if weights.len() != members.len() {
+                return Err(format!(
+                    "Please give one weight for each of the {} members!",
+                    members.len()
+                )
+                .into());
+            }
+            weights
+        }
+        None => vec![1.0; members.len()],
+    };
+    let db_int = &ctx.data().db;
+    // Check that every member is stored
+    let mut weighted_members: Vec<(u32, f64)> = vec![];
+    for (member, weight) in members.iter().zip(weights) {
+        match db_int.fetch_member(guild_id.0, member.user.id.0).await? {
+            Some(member_found) => weighted_members.push((member_found, weight)),
+            None => return Err(format!("{} is not a member that I know about! Help me learn about them using the `trackmember` command!", member.mention()).into()),
         }
     }
-    // Get db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Should have DB here")
-        .lock()
-        .await;
-
-    match db_int
-        .remove_tracked_member(
-            msg.guild_id.expect("Should be in a guild").0,
-            member.user.id.0,
+    match ctx
+        .data()
+        .model
+        .generate(
+            &Personality::Members(weighted_members),
+            DEFAULT_TEMPERATURE,
+            None,
+            SeedPosition::Start,
         )
         .await
     {
-        Ok(crate::client::database::errors::RemoveResult::Removed) => {
-            msg.reply(
-                &ctx.http,
-                format!(
-                    "Removed {} from the list of tracked members!",
-                    member.mention()
-                ),
-            )
-            .await;
-            Ok(())
-        }
-        Ok(crate::client::database::errors::RemoveResult::NotPresent) => {
-            msg.reply(
-                &ctx.http,
-                format!("{} is not a tracked member!", member.mention()),
-            )
-            .await;
+        Ok(sentence) => {
+            ctx.say(utils::sendable(ctx.serenity_context(), guild_id, &sentence).await)
+                .await?;
             Ok(())
         }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error occurred: {}", why));
-            return Ok(());
-        }
+        Err(why) => Err(utils::generation_error(why, "They")),
     }
 }
 
-#[command]
-#[description("List tracked members.")]
-#[required_permissions("MANAGE_MESSAGES")]
-#[min_args(0)]
-#[max_args(0)]
-pub async fn listtrackedmembers(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    // Get db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Should have DB here")
-        .lock()
-        .await;
+/// Who I sound like when someone mentions me or replies to me.
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum PersonaChoice {
+    Member,
+    Channel,
+    Server,
+}
 
+/// Show or set who I sound like when someone mentions me or replies to me.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn replypersona(
+    ctx: Context<'_>,
+    #[description = "Who to sound like"] persona: Option<PersonaChoice>,
+    #[description = "The member to sound like, if the persona is a member"] member: Option<Member>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+    let reply_persona = match (persona, member) {
+        (None, _) => {
+            let description = match db_int.fetch_reply_persona(guild_id.0).await? {
+                ReplyPersona::Member(member_id) => match db_int.fetch_member_user(member_id).await?
+                {
+                    Some(user_id) => UserId(user_id).mention().to_string(),
+                    None => "a member who is gone".to_owned(),
+                },
+                ReplyPersona::Channel => "the channel I'm talked to in".to_owned(),
+                ReplyPersona::Server => "the whole server".to_owned(),
+            };
+            ctx.say(format!(
+                "When I'm talked to in this server, I sound like {}.",
+                description
+            ))
+            .await?;
+            return Ok(());
+        }
+        (Some(PersonaChoice::Member), Some(member)) => {
+            match db_int.fetch_member(guild_id.0, member.user.id.0).await? {
+                Some(member_id) => ReplyPersona::Member(member_id),
+                None => return Err(format!("{} is not a member that I know about! Help me learn about them using the `trackmember` command!", member.mention()).into()),
+            }
+        }
+        (Some(PersonaChoice::Member), None) => {
+            return Err("Please pick the member to sound like!".into())
+        }
+        (Some(PersonaChoice::Channel), _) => ReplyPersona::Channel,
+        (Some(PersonaChoice::Server), _) => ReplyPersona::Server,
+    };
+    db_int.set_reply_persona(guild_id.0, reply_persona).await?;
+    ctx.say("Got it! That's who I'll sound like when I'm talked to from now on.")
+        .await?;
+    Ok(())
+}
+
+/// Begin learning about a member.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn trackmember(
+    ctx: Context<'_>,
+    #[description = "The member to learn about"] member: Member,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
     match db_int
-        .fetch_tracked_user_members(msg.guild_id.expect("Should be in a server").0)
-        .await
+        .add_tracked_member(guild_id.0, member.user.id.0)
+        .await?
     {
-        Ok(possible_results) => {
-            if let Some(tracked_members) = possible_results {
-                let mut message = serenity::utils::MessageBuilder::new();
-                message.push_bold_line("Current tracked members:");
-                let mut former_members = String::new();
-                for member in tracked_members.into_iter() {
-                    match Member::convert(ctx, msg.guild_id, None, &member.to_string()).await {
-                        Ok(current_member) => {
-                            message.push_line(current_member.mention());
-                        } // This assumes that any error in parsing the user ID into a member is indicative of the user no longer being a member of the server.
-                        Err(why) => {
-                            former_members.push_str(&format!("{}\n", member.to_string()));
-                        }
-                    }
-                }
-                message.push_bold_line("The following user IDs are tracked, but they are no longer members of this server. Please remove them using the `untrackmember` command:");
-                message.push(former_members);
-                msg.reply(&ctx.http, message.build()).await;
-            } else {
-                msg.reply(&ctx.http, "No members are being tracked.").await;
-            }
+        InsertResult::Added => {
+            ctx.say(format!(
+                "Added {} to list of tracked members!",
+                member.mention()
+            ))
+            .await?;
         }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error occurred: {}", why));
+        InsertResult::AlreadyPresent => {
+            ctx.say(format!("{} is already tracked!", member.mention()))
+                .await?;
         }
     }
     Ok(())
 }
 
-#[command]
-#[description("Track messages in a channel")]
-#[required_permissions("MANAGE_MESSAGES")]
-pub async fn trackchannel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    // Argument parsing
-    if args.len() == 0 {
-        msg.reply(&ctx.http, "Please specify a channel!").await;
-        return Ok(());
+/// Stop learning about a member.
+///
+/// Takes a user rather than a member, so people who have left the server can be removed too.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn untrackmember(
+    ctx: Context<'_>,
+    #[description = "The member to stop learning about"] user: User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+    match db_int.remove_tracked_member(guild_id.0, user.id.0).await? {
+        RemoveResult::Removed => {
+            ctx.say(format!(
+                "Removed {} from the list of tracked members!",
+                user.mention()
+            ))
+            .await?;
+        }
+        RemoveResult::NotPresent => {
+            ctx.say(format!("{} is not a tracked member!", user.mention()))
+                .await?;
+        }
     }
-    let channel: Channel;
-    match utils::parse_channel(
-        ctx,
-        msg.guild_id.expect("Should be in a guild"),
-        args.current().unwrap(),
-    )
-    .await
-    {
-        Ok(channel_found) => channel = channel_found,
-        Err(why) => {
-            msg.reply(&ctx.http, why).await;
+    Ok(())
+}
+
+/// List tracked members.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn listtrackedmembers(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+
+    let tracked_members = match db_int.fetch_tracked_user_members(guild_id.0).await? {
+        Some(tracked_members) => tracked_members,
+        None => {
+            ctx.say("No members are being tracked.").await?;
             return Ok(());
         }
+    };
+    let mut message = serenity::utils::MessageBuilder::new();
+    message.push_bold_line("Current tracked members:");
+    let mut former_members = String::new();
+    for member in tracked_members.into_iter() {
+        match guild_id.member(ctx, member).await {
+            Ok(current_member) => {
+                message.push_line(current_member.mention());
+            } // This assumes that any error in fetching the member is indicative of the user no longer being a member of the server.
+            Err(_why) => {
+                former_members.push_str(&format!("{}\n", member));
+            }
+        }
     }
-    // Get db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Should have DB here")
-        .lock()
-        .await;
+    if !former_members.is_empty() {
+        message.push_bold_line("The following user IDs are tracked, but they are no longer members of this server. Please remove them using the `untrackmember` command:");
+        message.push(former_members);
+    }
+    ctx.say(message.build()).await?;
+    Ok(())
+}
 
+/// Track messages in a channel.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn trackchannel(
+    ctx: Context<'_>,
+    #[description = "The channel to learn from"]
+    #[channel_types("Text")]
+    channel: Channel,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
     match db_int
-        .add_tracked_channel(
-            msg.guild_id.expect("Should be in a server").0,
-            msg.channel_id.0,
-        )
-        .await
+        .add_tracked_channel(guild_id.0, channel.id().0)
+        .await?
     {
-        Ok(crate::client::database::errors::InsertResult::Added) => {
-            msg.reply(
-                &ctx.http,
-                format!("Added {} to list of tracked channels!", channel.mention()),
-            )
-            .await;
-            return Ok(());
+        InsertResult::Added => {
+            // New messages are learned from now on, so learning from history starts below this command
+            db_int
+                .start_channel_backfill(channel.id().0, ctx.id())
+                .await?;
+            ctx.say(format!(
+                "Added {} to list of tracked channels!",
+                channel.mention()
+            ))
+            .await?;
         }
-        Ok(crate::client::database::errors::InsertResult::AlreadyPresent) => {
-            msg.reply(
-                &ctx.http,
-                format!("{} is already tracked!", channel.mention()),
-            )
-            .await;
-            return Ok(());
+        InsertResult::AlreadyPresent => {
+            ctx.say(format!("{} is already tracked!", channel.mention()))
+                .await?;
         }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error occurred: {}", why))
-                .await;
-            return Ok(());
+    }
+    Ok(())
+}
+
+/// Stop tracking messages in a channel.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn untrackchannel(
+    ctx: Context<'_>,
+    #[description = "The channel to stop learning from"]
+    #[channel_types("Text")]
+    channel: Channel,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+    let removed = db_int
+        .remove_tracked_channel(guild_id.0, channel.id().0)
+        .await?;
+    // Also tried for channels that are no longer tracked, in case deleting the webhook failed when they were untracked
+    let webhook_removed =
+        webhook::remove_channel_webhook(&ctx.serenity_context().http, db_int, channel.id()).await;
+    match removed {
+        RemoveResult::Removed => {
+            ctx.say(format!(
+                "Removed {} from the list of tracked channels!",
+                channel.mention()
+            ))
+            .await?;
+        }
+        RemoveResult::NotPresent => {
+            ctx.say(format!("{} is not a tracked channel!", channel.mention()))
+                .await?;
         }
     }
+    if let Err(why) = webhook_removed {
+        return Err(format!(
+            "I couldn't delete the webhook I post through in {} ({}). Run `untrackchannel` again to retry, or delete it in the channel settings.",
+            channel.mention(),
+            why
+        )
+        .into());
+    }
+    Ok(())
 }
 
-#[command]
-#[description("Stop tracking messages in a channel")]
-#[required_permissions("MANAGE_MESSAGES")]
-pub async fn untrackchannel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    // Argument parsing
-    if args.len() == 0 {
-        msg.reply(&ctx.http, "Please specify a channel!").await;
+/// Show or set how often I chime in to a channel on my own.
+///
+/// I chime in after a number of messages, or with a chance for each message, but never more than once per cooldown.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn chimein(
+    ctx: Context<'_>,
+    #[description = "The channel to chime in to"]
+    #[channel_types("Text")]
+    channel: Channel,
+    #[description = "Chime in after this many messages, 0 to turn off"] every: Option<u32>,
+    #[description = "The chance of chiming in after each message, from 0 to 1"]
+    #[min = 0.0]
+    #[max = 1.0]
+    probability: Option<f64>,
+    #[description = "The least number of seconds between two chime-ins"] cooldown: Option<u64>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+    let current = db_int.fetch_chime_settings(channel.id().0).await?;
+    if every.is_none() && probability.is_none() && cooldown.is_none() {
+        match current {
+            Some(settings) => {
+                ctx.say(describe_chime(&channel, &settings)).await?;
+            }
+            None => {
+                ctx.say(format!("I don't chime in to {}.", channel.mention()))
+                    .await?;
+            }
+        }
         return Ok(());
     }
-    let channel: Channel;
-    match utils::parse_channel(
-        ctx,
-        msg.guild_id.expect("Should be in a guild"),
-        args.current().unwrap(),
-    )
-    .await
-    {
-        Ok(channel_found) => channel = channel_found,
-        Err(why) => {
-            msg.reply(&ctx.http, why).await;
-            return Ok(());
+    // Unset options keep their current value
+    let mut settings = current.unwrap_or(ChimeSettings {
+        every_n_messages: None,
+        probability: 0.0,
+        cooldown_seconds: chime::DEFAULT_CHIME_COOLDOWN_SECONDS,
+    });
+    if let Some(every) = every {
+        settings.every_n_messages = Some(every).filter(|every| *every > 0);
+    }
+    if let Some(probability) = probability {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err("The probability must be a number from 0 to 1!".into());
         }
+        settings.probability = probability;
     }
-    // Get db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Should have DB here")
-        .lock()
-        .await;
-
-    match db_int
-        .remove_tracked_channel(
-            msg.guild_id.expect("Should be in a guild").0,
-            msg.channel_id.0,
-        )
-        .await
-    {
-        Ok(crate::client::database::errors::RemoveResult::Removed) => {
-            msg.reply(
-                &ctx.http,
-                format!(
-                    "Removed {} from the list of tracked channels!",
-                    channel.mention()
-                ),
+    if let Some(cooldown) = cooldown {
+        if cooldown < chime::MIN_CHIME_COOLDOWN_SECONDS {
+            return Err(format!(
+                "The cooldown must be at least {} seconds!",
+                chime::MIN_CHIME_COOLDOWN_SECONDS
             )
-            .await;
-            Ok(())
+            .into());
         }
-        Ok(crate::client::database::errors::RemoveResult::NotPresent) => {
-            msg.reply(
-                &ctx.http,
-                format!("{} is not a tracked channel!", channel.mention()),
-            )
-            .await;
-            Ok(())
+        settings.cooldown_seconds = cooldown;
+    }
+    db_int
+        .set_chime_settings(guild_id.0, channel.id().0, settings)
+        .await?;
+    ctx.say(describe_chime(&channel, &settings)).await?;
+    Ok(())
+}
+
+/// Describes how often the bot chimes in to a channel.
+fn describe_chime(channel: &Channel, settings: &ChimeSettings) -> String {
+    let mut triggers: Vec<String> = vec![];
+    if let Some(every_n_messages) = settings.every_n_messages {
+        triggers.push(format!("after every {} messages", every_n_messages));
+    }
+    if settings.probability > 0.0 {
+        triggers.push(format!(
+            "with a {}% chance after each message",
+            settings.probability * 100.0
+        ));
+    }
+    if triggers.is_empty() {
+        return format!(
+            "I won't chime in to {} until it's given a number of messages or a probability.",
+            channel.mention()
+        );
+    }
+    format!(
+        "I chime in to {} {}, at most once every {} seconds.",
+        channel.mention(),
+        triggers.join(" or "),
+        settings.cooldown_seconds
+    )
+}
+
+/// Stop chiming in to a channel on my own.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn stopchimein(
+    ctx: Context<'_>,
+    #[description = "The channel to stop chiming in to"]
+    #[channel_types("Text")]
+    channel: Channel,
+) -> Result<(), Error> {
+    let db_int = &ctx.data().db;
+    match db_int.remove_chime_settings(channel.id().0).await? {
+        RemoveResult::Removed => {
+            ctx.say(format!("I'll stop chiming in to {}!", channel.mention()))
+                .await?;
         }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error occurred: {}", why));
+        RemoveResult::NotPresent => {
+            ctx.say(format!("I don't chime in to {}!", channel.mention()))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// List tracked channels.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn listtrackedchannels(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+
+    let tracked_channels = match db_int.fetch_tracked_channels(guild_id.0).await? {
+        Some(tracked_channels) => tracked_channels,
+        None => {
+            ctx.say("No channels are being tracked.").await?;
             return Ok(());
         }
+    };
+    let mut message = serenity::utils::MessageBuilder::new();
+    message.push_bold_line("Current tracked channels:");
+    let mut former_channels = String::new();
+    for channel in tracked_channels.into_iter() {
+        match ChannelId(channel).to_channel(ctx).await {
+            Ok(current_channel) => {
+                message.push_line(current_channel.mention());
+            } // This assumes that any error in fetching the channel is indicative of the channel no longer being visible to the bot due to permissions, or due to the channel no longer existing.
+            Err(_why) => {
+                former_channels.push_str(&format!("{}\n", channel));
+            }
+        }
     }
+    if !former_channels.is_empty() {
+        message.push_bold_line("The following channel IDs are tracked, but they are either no longer visible to me or they no longer exist. Please check my permissions and/or remove them using the `untrackchannel` command:");
+        message.push(former_channels);
+    }
+    ctx.say(message.build()).await?;
+    Ok(())
 }
 
-#[command]
-#[description("List tracked channels.")]
-#[required_permissions("MANAGE_MESSAGES")]
-pub async fn listtrackedchannels(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    // Get db interface
-    let map = ctx.data.read().await;
-    let db_int = map
-        .get::<DbInterface>()
-        .expect("Should have DB here")
-        .lock()
-        .await;
+/// Learn from the message history of a tracked channel.
+///
+/// Only reads messages sent before the channel was tracked, since I learn newer ones as they come in, and picks up where the last run stopped.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn learn(
+    ctx: Context<'_>,
+    #[description = "The tracked channel to learn from"]
+    #[channel_types("Text")]
+    channel: Channel,
+    #[description = "The most messages to read this time"]
+    #[min = 1]
+    limit: Option<u64>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
 
-    match db_int
-        .fetch_tracked_channels(msg.guild_id.expect("Should be in a server").0)
-        .await
+    if !db_int
+        .is_tracked_channel(guild_id.0, channel.id().0)
+        .await?
     {
-        Ok(possible_results) => {
-            if let Some(tracked_channels) = possible_results {
-                let mut message = serenity::utils::MessageBuilder::new();
-                message.push_bold_line("Current tracked channels:");
-                let mut former_channels = String::new();
-                for channel in tracked_channels.into_iter() {
-                    match Channel::convert(ctx, msg.guild_id, None, &channel.to_string()).await {
-                        Ok(current_channel) => {
-                            message.push_line(current_channel.mention());
-                        } // This assumes that any error in parsing the channel ID into a channel is indicative of the channel no longer being visible to the bot due to permissions, or due to the channel no longer existing.
-                        Err(why) => {
-                            former_channels.push_str(&format!("{}\n", channel.to_string()));
-                        }
-                    }
+        return Err(format!(
+            "{} is not a tracked channel! Track it with the `trackchannel` command first.",
+            channel.mention()
+        )
+        .into());
+    }
+    // Resume from the oldest message processed by a previous run
+    let mut before: Option<MessageId> = None;
+    if let Some(backfill) = db_int.fetch_channel_backfill(channel.id().0).await? {
+        if backfill.complete {
+            return Err(
+                format!("I've already learned everything in {}!", channel.mention()).into(),
+            );
+        }
+        before = backfill.oldest_message_id.map(MessageId);
+    }
+
+    // Progress is shown by editing the reply as a regular message, since interaction responses can only be edited for a limited time
+    let mut progress = ctx
+        .say(format!("Learning from {}...", channel.mention()))
+        .await?
+        .into_message()
+        .await?;
+    let mut processed: u64 = 0;
+    let mut learned: u64 = 0;
+    // Tracked member IDs of the authors seen so far, so each author is only looked up once
+    let mut authors: HashMap<UserId, Option<u32>> = HashMap::new();
+    let complete = loop {
+        let page_size = match limit {
+            Some(limit) => BACKFILL_PAGE_SIZE.min(limit - processed),
+            None => BACKFILL_PAGE_SIZE,
+        };
+        if page_size == 0 {
+            break false;
+        }
+        let page = match channel
+            .id()
+            .messages(ctx, |retriever| {
+                if let Some(before) = before {
+                    retriever.before(before);
+                }
+                retriever.limit(page_size)
+            })
+            .await
+        {
+            Ok(page) => page,
+            Err(why) => {
+                progress
+                    .edit(ctx, |m| {
+                        m.content(format!(
+                            "Stopped after {} messages (learned from {}) because I couldn't read the history of {}: {}",
+                            processed,
+                            learned,
+                            channel.mention(),
+                            why
+                        ))
+                    })
+                    .await?;
+                return Ok(());
+            }
+        };
+        for message in page.iter() {
+            processed += 1;
+            // Same rules as learning from new messages
+            if message.author.bot || message.content.starts_with(crate::PREFIX) {
+                continue;
+            }
+            let member_id = match authors.get(&message.author.id) {
+                Some(member_id) => *member_id,
+                None => {
+                    let member_id = db_int
+                        .fetch_tracked_member(guild_id.0, message.author.id.0)
+                        .await?;
+                    authors.insert(message.author.id, member_id);
+                    member_id
                 }
-                message.push_bold_line("The following channel IDs are tracked, but they are either no longer visible to me or they no longer exist. Please check my permissions and/or remove them using the `untrackchannel` command:");
-                message.push(former_channels);
-                msg.reply(&ctx.http, message.build()).await;
-            } else {
-                msg.reply(&ctx.http, "No channels are being tracked.").await;
+            };
+            if let Some(member_id) = member_id {
+                if ctx
+                    .data()
+                    .model
+                    .learn(member_id, Some(channel.id().0), &message.content)
+                    .await?
+                {
+                    learned += 1;
+                }
+                // Saved after every learned message, so a run that fails partway never learns a message twice
+                db_int
+                    .update_channel_backfill(channel.id().0, Some(message.id.0), false)
+                    .await?;
             }
         }
-        Err(why) => {
-            msg.reply(&ctx.http, format!("An SQLx error occurred: {}", why));
+        // Pages are returned newest first
+        if let Some(oldest) = page.last() {
+            before = Some(oldest.id);
+        }
+        let reached_start = (page.len() as u64) < page_size;
+        db_int
+            .update_channel_backfill(channel.id().0, before.map(|id| id.0), reached_start)
+            .await?;
+        if reached_start {
+            break true;
         }
+        progress
+            .edit(ctx, |m| {
+                m.content(format!(
+                    "Learning from {}... processed {} messages so far (learned from {}).",
+                    channel.mention(),
+                    processed,
+                    learned
+                ))
+            })
+            .await?;
+    };
+
+    if complete {
+        progress
+            .edit(ctx, |m| {
+                m.content(format!(
+                    "Finished learning from {}! Processed {} messages and learned from {} of them.",
+                    channel.mention(),
+                    processed,
+                    learned
+                ))
+            })
+            .await?;
+    } else {
+        progress
+            .edit(ctx, |m| {
+                m.content(format!(
+                    "Processed {} messages in {} and learned from {} of them. Run `learn` again to keep going further back.",
+                    processed,
+                    channel.mention(),
+                    learned
+                ))
+            })
+            .await?;
     }
     Ok(())
 }
+
+/// Show or set how much of a generated sentence may match real messages in this server.
+///
+/// Sentences that copy more than this, or copy a long run of words of one message, are generated again. 1 lets everything through.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn originality(
+    ctx: Context<'_>,
+    #[description = "The largest share of a sentence that may match real messages, from 0 to 1"]
+    #[min = 0.0]
+    #[max = 1.0]
+    threshold: Option<f64>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+
+    let threshold = match threshold {
+        Some(threshold) if (0.0..=1.0).contains(&threshold) => threshold,
+        Some(_) => return Err("The threshold must be a number from 0 to 1!".into()),
+        None => {
+            let threshold = db_int.fetch_originality_threshold(guild_id.0).await?;
+            ctx.say(format!(
+                "Up to {}% of what I say may match real messages in this server.",
+                threshold * 100.0
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+    db_int
+        .set_originality_threshold(guild_id.0, threshold)
+        .await?;
+    ctx.say(format!(
+        "From now on, up to {}% of what I say may match real messages in this server!",
+        threshold * 100.0
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Show or set how many words of context are used to generate text in this server.
+///
+/// Higher orders stick closer to what members actually said.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn markovorder(
+    ctx: Context<'_>,
+    #[description = "How many words of context to use, from 1 to 3"]
+    #[autocomplete = "utils::autocomplete_markov_order"]
+    #[min = 1]
+    #[max = 3]
+    order: Option<u8>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("Should be in a guild");
+    let db_int = &ctx.data().db;
+
+    let markov_order = match order {
+        Some(markov_order) if (1..=MAX_MARKOV_ORDER).contains(&markov_order) => markov_order,
+        Some(_) => {
+            return Err(
+                format!("The order must be a number from 1 to {}!", MAX_MARKOV_ORDER).into(),
+            )
+        }
+        None => {
+            let markov_order = db_int.fetch_markov_order(guild_id.0).await?;
+            ctx.say(format!(
+                "I'm using {} words of context in this server.",
+                markov_order
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+    db_int.set_markov_order(guild_id.0, markov_order).await?;
+    ctx.say(format!(
+        "I'll use {} words of context in this server from now on!",
+        markov_order
+    ))
+    .await?;
+    Ok(())
+}
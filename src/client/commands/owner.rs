@@ -1,22 +1,14 @@
-use crate::ShardManagerContainer;
-use serenity::framework::standard::macros::command;
-use serenity::framework::standard::{Args, CommandResult};
-use serenity::model::prelude::*;
-use serenity::prelude::*;
-
-#[command]
-pub async fn stop(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let data = ctx.data.read().await;
-
-    if let Some(manager) = data.get::<ShardManagerContainer>() {
-        msg.reply(ctx, "Shutting down!").await?;
-        manager.lock().await.shutdown_all().await;
-    } else {
-        msg.reply(ctx, "There was a problem getting the shard manager")
-            .await?;
-
-        return Ok(());
-    }
-
+use crate::client::commands::{Context, Error};
+
+/// Shut the bot down.
+#[poise::command(slash_command, prefix_command, owners_only, ephemeral)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Shutting down!").await?;
+    ctx.framework()
+        .shard_manager()
+        .lock()
+        .await
+        .shutdown_all()
+        .await;
     Ok(())
 }
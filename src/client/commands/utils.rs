@@ -1,38 +1,228 @@
-use rand::Rng;
-use serenity::framework::standard::macros::command;
-use serenity::framework::standard::{Args, CommandResult};
-use serenity::model::prelude::*;
-use serenity::prelude::*;
-use serenity::utils::ArgumentConvert;
-
-use crate::client::commands::errors;
+use poise::AutocompleteChoice;
+use serenity::client::Context as SerenityContext;
+use serenity::model::id::{EmojiId, GuildId, UserId};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::client::commands::{Context, Error};
 use crate::client::database::errors::MarkovFetchResultError;
-use crate::client::database::interface::DbInterface;
+use crate::client::textgen::markov::{DEFAULT_TEMPERATURE, MAX_TEMPERATURE, MIN_TEMPERATURE};
+use crate::client::textgen::textprocessing::{self, Mention, MAX_MARKOV_ORDER};
 
-pub async fn parse_member(
-    ctx: &Context,
-    guild_id: GuildId,
-    string: &str,
-) -> std::result::Result<Member, errors::MemberNotFoundError> {
-    match Member::convert(ctx, Some(guild_id), None, string).await {
-        Ok(member) => Ok(member),
-        Err(_why) => Err(errors::MemberNotFoundError::MemberNotFound(format!(
-            "The member specified by {} was not found!",
-            string
-        ))),
+/** Suggests a few temperatures, from predictable to adventurous. */
+pub async fn autocomplete_temperature(
+    _ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice<f64>> {
+    let partial = partial.to_owned();
+    [
+        ("predictable", MIN_TEMPERATURE),
+        ("normal", DEFAULT_TEMPERATURE),
+        ("adventurous", 2.0),
+        ("chaotic", MAX_TEMPERATURE),
+    ]
+    .into_iter()
+    .map(|(label, temperature)| AutocompleteChoice {
+        name: format!("{} ({})", temperature, label),
+        value: temperature,
+    })
+    .filter(move |choice| choice.name.contains(partial.as_str()))
+}
+
+/** Suggests every supported Markov order. */
+pub async fn autocomplete_markov_order(
+    _ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice<u8>> {
+    let partial = partial.to_owned();
+    (1..=MAX_MARKOV_ORDER)
+        .map(|markov_order| AutocompleteChoice {
+            name: format!(
+                "{} {} of context",
+                markov_order,
+                if markov_order == 1 { "word" } else { "words" }
+            ),
+            value: markov_order,
+        })
+        .filter(move |choice| choice.name.starts_with(partial.as_str()))
+}
+
+/** Checks a temperature given to a command, since slash commands enforce the bounds themselves but prefix commands don't. */
+pub fn parse_temperature(temperature: Option<f64>) -> Result<f64, Error> {
+    let temperature = temperature.unwrap_or(DEFAULT_TEMPERATURE);
+    if !(MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&temperature) {
+        return Err(format!(
+            "The temperature must be a number from {} to {}!",
+            MIN_TEMPERATURE, MAX_TEMPERATURE
+        )
+        .into());
+    }
+    Ok(temperature)
+}
+
+/** Options of a prefix command written as `name:value` words, e.g. `temperature:2`, and the word or phrase to start from that follows them. */
+#[derive(Debug, Default, PartialEq)]
+pub struct PrefixOptions {
+    options: HashMap<String, String>,
+    pub seed: Option<String>,
+}
+
+impl PrefixOptions {
+    /** Reads the arguments of a prefix command. The first `leading` arguments, like the member to mimic, were already read by poise and are skipped. Any of the options in `names` come next, and everything after them is the seed.
+
+    Poise fills optional parameters greedily, so a seed like `anywhere you go` would lose its first word to the position parameter. Asking for options by name keeps every seed whole. */
+    pub fn parse(args: &str, leading: usize, names: &[&str]) -> Self {
+        let mut rest = args.trim_start();
+        for _ in 0..leading {
+            rest = skip_argument(rest).trim_start();
+        }
+        let mut options = HashMap::new();
+        loop {
+            let (word, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            match word.split_once(':') {
+                Some((name, value)) if names.contains(&name) && !value.is_empty() => {
+                    options.insert(name.to_owned(), value.to_owned());
+                    rest = after.trim_start();
+                }
+                _ => break,
+            }
+        }
+        let seed = rest.trim();
+        Self {
+            options,
+            seed: (!seed.is_empty()).then(|| seed.to_owned()),
+        }
+    }
+
+    /** Returns the value given for an option, if it was given. */
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        match self.options.get(name) {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(format!("\"{}\" is not a valid {}!", value, name).into()),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/** Returns what is left after the first argument, which like in poise is a single word or a phrase in double quotes. */
+fn skip_argument(args: &str) -> &str {
+    match args.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').map_or("", |(_, rest)| rest),
+        None => args
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest),
+    }
+}
+
+/** Turns a failed generation into a reply. `speaker` is whoever was being imitated, e.g. a member mention. */
+pub fn generation_error(why: MarkovFetchResultError, speaker: &str) -> Error {
+    match why {
+        MarkovFetchResultError::NotEnoughWords(min_sentence_length, generated_sentence_length) => format!("I didn't know enough words to generate a sentence of at least {} words. (The longest I was able to get was {} words).", min_sentence_length, generated_sentence_length).into(),
+        MarkovFetchResultError::SeedNotFound(seed) => format!(
+            "{} has never said \"{}\", so I can't start from it!",
+            speaker, seed
+        )
+        .into(),
+        MarkovFetchResultError::NotOriginal => format!(
+            "Everything I came up with sounded too much like something {} really said. Try again once I've learned more!",
+            speaker
+        )
+        .into(),
+        MarkovFetchResultError::SqlxError(why) => why.into(),
     }
 }
 
-pub async fn parse_channel(
-    ctx: &Context,
+/** Returns the names a member goes by in a server, their display name first and then their username. Members are only cached with the privileged server members intent, which the bot doesn't ask for, so they are usually fetched from discord. Users who left the server only have their username. */
+pub async fn member_names(
+    ctx: &SerenityContext,
     guild_id: GuildId,
-    string: &str,
-) -> std::result::Result<Channel, errors::ChannelNotFoundError> {
-    match Channel::convert(ctx, Some(guild_id), None, string).await {
-        Ok(channel) => Ok(channel),
-        Err(_why) => Err(errors::ChannelNotFoundError::ChannelNotFound(format!(
-            "The channel specified by {} was not found!",
-            string
-        ))),
+    user_id: UserId,
+) -> Vec<String> {
+    match guild_id.member(ctx, user_id).await {
+        Ok(member) => vec![member.display_name().into_owned(), member.user.name],
+        Err(_) => user_id
+            .to_user(ctx)
+            .await
+            .map_or_else(|_| vec![], |user| vec![user.name]),
+    }
+}
+
+/** Makes generated text ready to send in a server. Mentions are written out as names, so they can't ping anyone even if mentions were allowed. Custom emoji the bot can't use anymore, because it left their server, they were deleted or they are locked behind a boost the server lost, fall back to their name. */
+pub async fn sendable(ctx: &SerenityContext, guild_id: GuildId, text: &str) -> String {
+    let cache = &ctx.cache;
+    // Looked up before rendering, once per mentioned user, since rendering can't wait on discord
+    let mut user_names = HashMap::new();
+    for user_id in textprocessing::mentioned_users(text) {
+        let names = member_names(ctx, guild_id, UserId(user_id)).await;
+        if let Some(name) = names.into_iter().next() {
+            user_names.insert(user_id, name);
+        }
+    }
+    let text = textprocessing::render_mentions(text, |mention| match mention {
+        Mention::User(user_id) => format!(
+            "@{}",
+            user_names
+                .get(&user_id)
+                .map_or("unknown-user", String::as_str)
+        ),
+        Mention::Role(role_id) => match cache.role(guild_id, role_id) {
+            // The everyone role is already called @everyone
+            Some(role) => format!("@{}", role.name.trim_start_matches('@')),
+            None => "@deleted-role".to_owned(),
+        },
+        Mention::Channel(channel_id) => {
+            let name = cache.guild_channel_field(channel_id, |channel| channel.name.clone());
+            format!("#{}", name.as_deref().unwrap_or("deleted-channel"))
+        }
+    });
+    textprocessing::fallback_custom_emoji(&text, |emoji_id| {
+        cache.guilds().into_iter().any(|guild_id| {
+            cache
+                .guild_field(guild_id, |guild| {
+                    guild
+                        .emojis
+                        .get(&EmojiId(emoji_id))
+                        .is_some_and(|emoji| emoji.available)
+                })
+                .unwrap_or(false)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 3] = ["temperature", "position", "as_member"];
+
+    #[test]
+    fn test_prefix_options_keep_seed_whole() {
+        let options = PrefixOptions::parse("<@123> anywhere you go", 1, &NAMES);
+        assert_eq!(Some("anywhere you go".to_owned()), options.seed);
+        assert_eq!(None, options.get::<f64>("temperature").unwrap());
+        let options = PrefixOptions::parse("<@123> 1 more thing", 1, &NAMES);
+        assert_eq!(Some("1 more thing".to_owned()), options.seed);
+        let options = PrefixOptions::parse("\"Some Name\" true story", 1, &NAMES);
+        assert_eq!(Some("true story".to_owned()), options.seed);
+        assert_eq!(None, options.get::<bool>("as_member").unwrap());
+    }
+
+    #[test]
+    fn test_prefix_options_by_name() {
+        let options = PrefixOptions::parse(
+            "<@123> temperature:2.5  as_member:true the cat position:start",
+            1,
+            &NAMES,
+        );
+        assert_eq!(Some(2.5), options.get::<f64>("temperature").unwrap());
+        assert_eq!(Some(true), options.get::<bool>("as_member").unwrap());
+        // Options only count before the seed
+        assert_eq!(Some("the cat position:start".to_owned()), options.seed);
+        assert!(PrefixOptions::parse("temperature:hot", 0, &NAMES)
+            .get::<f64>("temperature")
+            .is_err());
+        assert_eq!(None, PrefixOptions::parse("  ", 0, &NAMES).seed);
     }
 }
@@ -0,0 +1,17 @@
+use crate::client::chime::ChimeState;
+use crate::client::database::interface::DbInterface;
+use crate::client::textgen::model::TextModel;
+use serenity::prelude::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/** State shared by every command and event. */
+pub struct Data {
+    pub db: Arc<DbInterface>,
+    /** Learns and generates text. Text only goes through this, so the model behind it can be swapped out. */
+    pub model: Arc<dyn TextModel>,
+    /** Chime-in state for each channel, by channel_id. */
+    pub chime: Mutex<HashMap<u64, ChimeState>>,
+    /** Channels with a round of "Who said it?" going on, by channel_id. */
+    pub games: Mutex<HashSet<u64>>,
+}
@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod entities;
 pub mod errors;
 pub mod interface;
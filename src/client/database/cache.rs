@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use crate::client::textgen::transitions::TransitionModel;
+
+/** How much memory the cached models of every member may take up together, roughly. */
+pub const MODEL_CACHE_BUDGET_BYTES: usize = 128 * 1024 * 1024;
+
+/// The models of the members that were generated from most recently, by member_id.
+///
+/// When the models take up more than the budget, the least recently used ones are dropped. They are loaded again the next time they are needed.
+#[derive(Debug)]
+pub struct ModelCache {
+    models: HashMap<u32, CachedModel>,
+    budget_bytes: usize,
+    used_bytes: usize,
+    /** Counts every use of a model, so the least recently used one can be found. */
+    clock: u64,
+    /** Counts the messages of each member that started or finished being learned, so a model loaded while one of the member's messages was learned can be told apart. Messages of other members don't matter to it. */
+    learned: HashMap<u32, u64>,
+    /** The word_ids of the begin and end of message tokens, once they are known. */
+    pub sentinel_word_ids: Option<(i32, i32)>,
+}
+
+/** A cached model, along with when it was last used and when it was cached, both by the clock of the cache. */
+#[derive(Debug)]
+struct CachedModel {
+    model: TransitionModel,
+    last_used: u64,
+    cached_at: u64,
+}
+
+impl ModelCache {
+    pub fn new(budget_bytes: usize) -> ModelCache {
+        ModelCache {
+            models: HashMap::new(),
+            budget_bytes,
+            used_bytes: 0,
+            clock: 0,
+            learned: HashMap::new(),
+            sentinel_word_ids: None,
+        }
+    }
+
+    /** Returns the model of a member if it is cached, and marks it as recently used. */
+    pub fn get(&mut self, member_id: u32) -> Option<&TransitionModel> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.models.get_mut(&member_id).map(|cached| {
+            cached.last_used = clock;
+            &cached.model
+        })
+    }
+
+    /** Returns how many messages of a member started or finished being learned so far. Pass it to `insert` along with a model loaded afterwards. */
+    pub fn learned(&self, member_id: u32) -> u64 {
+        self.learned.get(&member_id).copied().unwrap_or(0)
+    }
+
+    /** Caches the model of a member, dropping the least recently used models if the budget is exceeded. A model is kept even if it is larger than the budget on its own, until another one is cached.
+    A model that was loaded while one of the member's messages was learned may be missing some of its transitions, or already have some of them, so it isn't cached. Returns whether the model was cached. */
+    pub fn insert(
+        &mut self,
+        member_id: u32,
+        model: TransitionModel,
+        learned_before_loading: u64,
+    ) -> bool {
+        if learned_before_loading != self.learned(member_id) {
+            return false;
+        }
+        self.clock += 1;
+        self.used_bytes += model.approximate_bytes();
+        let cached = CachedModel {
+            model,
+            last_used: self.clock,
+            cached_at: self.clock,
+        };
+        if let Some(replaced) = self.models.insert(member_id, cached) {
+            self.used_bytes -= replaced.model.approximate_bytes();
+        }
+        self.evict(member_id);
+        true
+    }
+
+    /** Call before the transitions of a message of a member are written to the database, so no model loaded while they are written gets cached. Pass what it returns to `learn` once they are written. */
+    pub fn start_learning(&mut self, member_id: u32) -> Option<u64> {
+        *self.learned.entry(member_id).or_default() += 1;
+        self.models.get(&member_id).map(|cached| cached.cached_at)
+    }
+
+    /** Counts the transitions of a newly learned message towards a member, if their model is cached. Only the model that was cached when learning started is known to be missing them, so a model cached in the meantime is dropped instead. Uncached models pick them up from the database when they are loaded. */
+    pub fn learn(&mut self, member_id: u32, word_ids: &[i32], cached_at_start: Option<u64>) {
+        *self.learned.entry(member_id).or_default() += 1;
+        match self.models.get_mut(&member_id) {
+            Some(cached) if Some(cached.cached_at) == cached_at_start => {
+                let bytes_before = cached.model.approximate_bytes();
+                cached.model.learn(word_ids);
+                self.used_bytes += cached.model.approximate_bytes() - bytes_before;
+                self.evict(member_id);
+            }
+            Some(_) => {
+                if let Some(dropped) = self.models.remove(&member_id) {
+                    self.used_bytes -= dropped.model.approximate_bytes();
+                }
+            }
+            None => {}
+        }
+    }
+
+    /** Drops the least recently used models, other than the one being used, until the budget is met. */
+    fn evict(&mut self, keep_member_id: u32) {
+        while self.used_bytes > self.budget_bytes {
+            let least_recently_used = self
+                .models
+                .iter()
+                .filter(|(member_id, _)| **member_id != keep_member_id)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(member_id, _)| *member_id);
+            match least_recently_used.and_then(|member_id| self.models.remove(&member_id)) {
+                Some(cached) => self.used_bytes -= cached.model.approximate_bytes(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn model(word_ids: &[i32]) -> TransitionModel {
+        let mut model = TransitionModel::default();
+        model.learn(word_ids);
+        model
+    }
+
+    #[test]
+    fn test_cache_learns_into_cached_models_only() {
+        let mut cache = ModelCache::new(usize::MAX);
+        cache.insert(1, model(&[1, 2]), cache.learned(1));
+        let cached_at_start = cache.start_learning(1);
+        cache.learn(1, &[2, 3], cached_at_start);
+        let cached_at_start = cache.start_learning(2);
+        cache.learn(2, &[2, 3], cached_at_start);
+        assert_eq!(
+            vec![(3, 1)],
+            cache.get(1).expect("Should be cached").successors(&[2])
+        );
+        assert!(cache.get(2).is_none());
+    }
+
+    #[test]
+    fn test_cache_skips_model_loaded_while_learning() {
+        let mut cache = ModelCache::new(usize::MAX);
+        let learned_before_loading = cache.learned(1);
+        let cached_at_start = cache.start_learning(1);
+        assert!(!cache.insert(1, model(&[1, 2]), learned_before_loading));
+        cache.learn(1, &[2, 3], cached_at_start);
+        assert!(cache.get(1).is_none());
+    }
+
+    #[test]
+    fn test_cache_drops_model_loaded_after_learning_started() {
+        let mut cache = ModelCache::new(usize::MAX);
+        let cached_at_start = cache.start_learning(1);
+        // The load already reads the transitions written for the message being learned
+        assert!(cache.insert(1, model(&[1, 2, 3]), cache.learned(1)));
+        cache.learn(1, &[2, 3], cached_at_start);
+        assert!(cache.get(1).is_none());
+        assert!(cache.insert(1, model(&[1, 2, 3]), cache.learned(1)));
+        assert_eq!(
+            vec![(3, 1)],
+            cache.get(1).expect("Should be cached").successors(&[2])
+        );
+    }
+
+    #[test]
+    fn test_cache_keeps_model_loaded_while_learning_for_others() {
+        let mut cache = ModelCache::new(usize::MAX);
+        let learned_before_loading = cache.learned(1);
+        let cached_at_start = cache.start_learning(2);
+        cache.learn(2, &[2, 3], cached_at_start);
+        assert!(cache.insert(1, model(&[1, 2]), learned_before_loading));
+        assert!(cache.get(1).is_some());
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let bytes = model(&[1, 2, 3]).approximate_bytes();
+        let mut cache = ModelCache::new(bytes * 2);
+        cache.insert(1, model(&[1, 2, 3]), cache.learned(1));
+        cache.insert(2, model(&[4, 5, 6]), cache.learned(2));
+        // Using the first model makes the second one the least recently used
+        assert!(cache.get(1).is_some());
+        cache.insert(3, model(&[7, 8, 9]), cache.learned(3));
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(3).is_some());
+    }
+
+    #[test]
+    fn test_cache_keeps_model_larger_than_budget() {
+        let mut cache = ModelCache::new(0);
+        cache.insert(1, model(&[1, 2, 3]), cache.learned(1));
+        assert!(cache.get(1).is_some());
+        cache.insert(2, model(&[4, 5, 6]), cache.learned(2));
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+    }
+}
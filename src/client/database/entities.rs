@@ -40,3 +40,59 @@ pub struct MemberWord {
     pub next_word_id: u32,
     pub frequency: u64,
 }
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChannelBackfill {
+    pub oldest_message_id: Option<u64>,
+    pub complete: bool,
+}
+/** A player's place on the "Who said it?" leaderboard of a server. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameScore {
+    pub user_id: u64,
+    /** The rounds the player guessed first. */
+    pub wins: u32,
+}
+/** A webhook the bot made in a channel, which it can post through as anyone. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelWebhook {
+    pub webhook_id: u64,
+    pub token: String,
+}
+/** Whose way of talking generated text imitates. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Personality {
+    /** One or more members, as `(member_id, weight)` pairs. Each member's transition counts are multiplied by their weight before being added together. */
+    Members(Vec<(u32, f64)>),
+    /** Everything learned in a channel. */
+    Channel(u64),
+    /** Everything learned in every channel of a server. */
+    Server(u64),
+}
+/** Who the bot talks like when it is mentioned or replied to in a server. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyPersona {
+    /** A single member, by member_id. */
+    Member(u32),
+    /** The channel the bot was talked to in. */
+    Channel,
+    /** The whole server. */
+    Server,
+}
+/** How a channel is set up to have the bot join the conversation on its own. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChimeSettings {
+    /** Chime in after this many messages. */
+    pub every_n_messages: Option<u32>,
+    /** The chance of chiming in after each message, from 0 to 1. */
+    pub probability: f64,
+    /** The least time between two chime-ins. */
+    pub cooldown_seconds: u64,
+}
+/** Where a seed ends up in a generated sentence. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedPosition {
+    /** The sentence starts with the seed. */
+    Start,
+    /** The sentence grows backwards and forwards from the seed, so it can be anywhere in it. */
+    Anywhere,
+}
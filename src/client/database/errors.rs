@@ -15,6 +15,8 @@ pub enum RemoveResult {
 #[derive(Debug)]
 pub enum MarkovFetchResultError {
     NotEnoughWords(u8, u32),
+    SeedNotFound(String),
+    NotOriginal,
     SqlxError(sqlx::Error),
 }
 
@@ -25,6 +27,8 @@ impl std::error::Error for MarkovFetchResultError {
                 ref desired_sentence_length,
                 ref words_collected,
             ) => None,
+            MarkovFetchResultError::SeedNotFound(ref _seed) => None,
+            MarkovFetchResultError::NotOriginal => None,
             MarkovFetchResultError::SqlxError(ref sqlxerror) => Some(sqlxerror),
         }
     }
@@ -37,7 +41,16 @@ impl std::fmt::Display for MarkovFetchResultError {
                 ref desired_sentence_length,
                 ref words_collected,
             ) => {
-                write!(f, "The provided member does not have enough data stored for a sentence of {} words (Only know {} words)", desired_sentence_length, words_collected)
+                write!(f, "The provided member does not have enough data stored for a sentence of at least {} words (Only know {} words)", desired_sentence_length, words_collected)
+            }
+            MarkovFetchResultError::SeedNotFound(ref seed) => {
+                write!(f, "The seed \"{}\" was never used", seed)
+            }
+            MarkovFetchResultError::NotOriginal => {
+                write!(
+                    f,
+                    "Every generated sentence copied learned messages too closely"
+                )
             }
             MarkovFetchResultError::SqlxError(ref sqlxerror) => {
                 write!(f, "SQLx Error: {}", sqlxerror)
@@ -1,87 +1,47 @@
-use crate::client::database::errors;
-use crate::client::textgen::textprocessing;
-use itertools::Itertools;
-use serenity::prelude::{Mutex, TypeMapKey};
+use crate::client::database::cache::{self, ModelCache};
+use crate::client::database::{entities, errors};
+use crate::client::textgen::model::{ModelStats, TextModel, TransitionSource};
+use crate::client::textgen::transitions::TransitionModel;
+use crate::client::textgen::{markov, originality, textprocessing};
+use async_trait::async_trait;
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{Pool, Postgres};
-use std::collections::HashMap;
-use std::sync::Arc;
-
-/** The DDL for the database schema */
-const DB_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS servers (
-	server_id BIGINT NOT NULL,
-	PRIMARY KEY (server_id)
-);
-CREATE TABLE IF NOT EXISTS users (
-    user_id BIGINT NOT NULL,
-    PRIMARY KEY (user_id)
-);
-CREATE TABLE IF NOT EXISTS members (
-    member_id SERIAL NOT NULL,
-    user_id BIGINT NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
-    server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
-    PRIMARY KEY (member_id)
-);
-CREATE TABLE IF NOT EXISTS channels (
-    channel_id BIGINT NOT NULL,
-    server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
-    PRIMARY KEY (channel_id)
-);
-CREATE TABLE IF NOT EXISTS tracked_channels (
-    channel_id BIGINT NOT NULL REFERENCES channels (channel_id) ON DELETE CASCADE
-);
-CREATE TABLE IF NOT EXISTS tracked_members (
-    member_id INTEGER NOT NULL REFERENCES members (member_id) ON DELETE CASCADE
-);
-CREATE TABLE IF NOT EXISTS words (
-    word_id SERIAL NOT NULL,
-    word TEXT NOT NULL,
-    PRIMARY KEY (word_id),
-    UNIQUE (word)    
-);
-CREATE TABLE IF NOT EXISTS member_words (
-    word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
-    member_id INTEGER NOT NULL REFERENCES members (member_id) ON DELETE CASCADE,
-    next_word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
-    frequency BIGINT NOT NULL
-);";
-
-/** SQL commands to delete everything. */
-const SQL_REINIT_DB_SCHEMA: &str = "DELETE FROM servers;
-DELETE FROM users;
-DELETE FROM members;
-DELETE FROM channels;
-DELETE FROM tracked_channels;
-DELETE FROM tracked_members;
-DELETE FROM words;
-DELETE FROM member_words;";
-
-/** A wrapper and interface struct for a Postgres pool. This is intended to be made and reside in the data attribute of serenity::prelude::Context such that the pool can be accessed from the Context. */
-pub struct DbInterface {
-    db: Pool<Postgres>,
-}
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 
-impl TypeMapKey for DbInterface {
-    type Value = Arc<Mutex<DbInterface>>;
-}
+/** The numbered schema migrations in `migrations/`. Applied versions are recorded in the `_sqlx_migrations` table, so only new migrations are run on startup. */
+static MIGRATOR: Migrator = sqlx::migrate!();
 
-/** Represents the possible states for the database when emitting DDL */
-#[derive(Debug)]
-pub enum DbSchemaInitialState {
-    AlreadyPresent,
-    NotPresent,
+/** A wrapper and interface struct for a Postgres pool. It is shared with every command and event through the framework's user data. */
+pub struct DbInterface {
+    pub(crate) db: Pool<Postgres>,
+    /** The transitions of recently generated from members, so generation doesn't need a query for every word. */
+    cache: Mutex<ModelCache>,
 }
 
 impl DbInterface {
-    /** Creates the pool wrapper. */
+    /** Creates the pool wrapper and brings the schema up to date. */
     pub async fn new(database_url: &str) -> DbInterface {
-        DbInterface {
+        let db_int = DbInterface {
             db: PgPoolOptions::new()
                 .max_connections(10)
-                .connect(&database_url)
+                .connect(database_url)
                 .await
                 .expect("Should be able to create pool for DbInterface"),
-        }
+            cache: Mutex::new(ModelCache::new(cache::MODEL_CACHE_BUDGET_BYTES)),
+        };
+        db_int
+            .migrate()
+            .await
+            .expect("Should be able to apply database migrations");
+        db_int
+    }
+
+    /** Applies any migrations that have not been applied to the database yet. */
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        MIGRATOR.run(&self.db).await?;
+        Ok(())
     }
 
     /** Returns member_id if the user specified by the server_id and user_id is a tracked member*/
@@ -191,7 +151,7 @@ impl DbInterface {
         .fetch_all(&self.db.clone())
         .await {
             Ok(record_opt_vec) => {
-                if record_opt_vec.len() == 0 {
+                if record_opt_vec.is_empty() {
                     Ok(None)
                 } else {
                     Ok(Some(record_opt_vec.into_iter().map(|record|record.member_id as u32).collect()))
@@ -213,7 +173,7 @@ impl DbInterface {
             server_id as i64
         ).fetch_all(&self.db).await {
             Ok(rows) => {
-                if rows.len() == 0 {
+                if rows.is_empty() {
                     Ok(None)
                 } else {
                     Ok(Some(rows.into_iter().map(|record|record.user_id as u64).collect()))
@@ -250,6 +210,17 @@ impl DbInterface {
         }
     }
 
+    /** Returns the user_id of a member, if the member is stored */
+    pub async fn fetch_member_user(&self, member_id: u32) -> Result<Option<u64>, sqlx::Error> {
+        Ok(sqlx::query!(
+            "SELECT user_id FROM members WHERE member_id = $1;",
+            member_id as i32
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .map(|record| record.user_id as u64))
+    }
+
     /** Returns a vector of member_id for stored members for a server*/
     pub async fn fetch_members(&self, server_id: u64) -> Result<Option<Vec<u32>>, sqlx::Error> {
         match sqlx::query!(
@@ -260,7 +231,7 @@ impl DbInterface {
         .await
         {
             Ok(record_opt_vec) => {
-                if record_opt_vec.len() == 0 {
+                if record_opt_vec.is_empty() {
                     Ok(None)
                 } else {
                     Ok(Some(
@@ -303,7 +274,7 @@ impl DbInterface {
         )
         .fetch_all(&self.db.clone())
         .await?;
-        if records.len() == 0 {
+        if records.is_empty() {
             Ok(None)
         } else {
             Ok(Some(
@@ -367,141 +338,892 @@ impl DbInterface {
         }
     }
 
-    // Fetches weighted random words for the member
-    pub async fn fetch_random_member_words_into_sentence(
+    /** Returns how a channel is set up to have the bot chime in, if it is. */
+    pub async fn fetch_chime_settings(
         &self,
-        member_id: u32,
-        desired_sentence_length: u8,
-    ) -> Result<String, errors::MarkovFetchResultError> {
-        // Initialize vector
-        let mut word_ids: Vec<i32> = vec![];
-        // Get sum of frequencies, so we can normalize and use rand function
-        let freq_sum: sqlx::types::BigDecimal;
-        if let Some(freq_sum_record) = sqlx::query!(
-            "SELECT sum(frequency) FROM member_words WHERE member_id = $1",
-            member_id as i64
+        channel_id: u64,
+    ) -> Result<Option<entities::ChimeSettings>, sqlx::Error> {
+        Ok(sqlx::query!(
+            "SELECT every_n_messages, probability, cooldown_seconds FROM channel_chime_settings WHERE channel_id = $1;",
+            channel_id as i64
         )
-        .fetch_optional(&self.db.clone())
+        .fetch_optional(&self.db)
         .await?
-        {
-            freq_sum = freq_sum_record
-                .sum
-                .expect("Should be able to get value of sum");
+        .map(|record| entities::ChimeSettings {
+            every_n_messages: record.every_n_messages.map(|every_n_messages| every_n_messages as u32),
+            probability: record.probability,
+            cooldown_seconds: record.cooldown_seconds as u64,
+        }))
+    }
+
+    /** Sets up a channel to have the bot chime in, replacing any previous settings. */
+    pub async fn set_chime_settings(
+        &self,
+        server_id: u64,
+        channel_id: u64,
+        settings: entities::ChimeSettings,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO servers(server_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO channels(channel_id, server_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            channel_id as i64,
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO channel_chime_settings (channel_id, every_n_messages, probability, cooldown_seconds) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (channel_id) DO UPDATE SET every_n_messages = EXCLUDED.every_n_messages, probability = EXCLUDED.probability, cooldown_seconds = EXCLUDED.cooldown_seconds;",
+            channel_id as i64,
+            settings.every_n_messages.map(|every_n_messages| every_n_messages as i32),
+            settings.probability,
+            settings.cooldown_seconds as i32
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Stops the bot from chiming in to a channel. */
+    pub async fn remove_chime_settings(
+        &self,
+        channel_id: u64,
+    ) -> Result<errors::RemoveResult, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM channel_chime_settings WHERE channel_id = $1",
+            channel_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        if result.rows_affected() > 0 {
+            Ok(errors::RemoveResult::Removed)
         } else {
-            return Err(errors::MarkovFetchResultError::NotEnoughWords(
-                desired_sentence_length,
-                0,
-            ));
+            Ok(errors::RemoveResult::NotPresent)
         }
-        //Not optimal conversion, but it will have to do :<
-        let freq_sum = freq_sum
-            .to_string()
-            .parse::<f64>()
-            .expect("Should be able to convert BigDecimal to f64");
-        // Choose random word id of a member. TODO: Make sure that this doesn't result in faulty word generation
-        if let Some(word_id) = sqlx::query!(
-            "SELECT word_id, next_word_id FROM member_words WHERE member_id = $1 ORDER BY random() LIMIT 1;",
-            member_id as i32
+    }
+
+    /** Returns the member_id of the member whose message should be learned from, if both they and the channel it was sent in are tracked. */
+    pub async fn fetch_learning_member(
+        &self,
+        server_id: u64,
+        channel_id: u64,
+        user_id: u64,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        if !self.is_tracked_channel(server_id, channel_id).await? {
+            return Ok(None);
+        }
+        self.fetch_tracked_member(server_id, user_id).await
+    }
+
+    /** Runs a message sent by a member through pre-processing and adds its words to the member's words, and to the words of the channel it was sent in if there is one. Returns whether the message had anything to learn from. */
+    pub async fn learn_member_message(
+        &self,
+        member_id: u32,
+        channel_id: Option<u64>,
+        content: &str,
+    ) -> Result<bool, errors::AddMemberWordsError> {
+        let tokens = textprocessing::message_tokens(content);
+        if tokens.is_empty() {
+            return Ok(false);
+        }
+        let word_ids = self
+            .add_words(tokens.iter().map(|token| token.key.clone()).collect())
+            .await?;
+        self.add_word_forms(&word_ids, &tokens).await?;
+        self.add_member_word_ids(member_id, &word_ids).await?;
+        self.add_message_shingles(member_id, &word_ids).await?;
+        if let Some(channel_id) = channel_id {
+            self.add_channel_word_ids(channel_id, &word_ids).await?;
+        }
+        Ok(true)
+    }
+
+    /** Returns the progress of the history backfill for a channel, if one was ever started. */
+    pub async fn fetch_channel_backfill(
+        &self,
+        channel_id: u64,
+    ) -> Result<Option<entities::ChannelBackfill>, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT oldest_message_id, complete FROM channel_backfills WHERE channel_id = $1;",
+            channel_id as i64
         )
-        .fetch_optional(&self.db.clone())
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(record.map(|record| entities::ChannelBackfill {
+            oldest_message_id: record.oldest_message_id.map(|id| id as u64),
+            complete: record.complete,
+        }))
+    }
+
+    /** Starts the history backfill of a channel below `before_message_id`, the point where learning from new messages began, so no message is learned twice. Progress made by an earlier run is kept. Expects the channel to be tracked. */
+    pub async fn start_channel_backfill(
+        &self,
+        channel_id: u64,
+        before_message_id: u64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO channel_backfills (channel_id, oldest_message_id, complete) VALUES ($1, $2, FALSE)
+             ON CONFLICT (channel_id) DO NOTHING;",
+            channel_id as i64,
+            before_message_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Records the oldest message processed by the history backfill of a channel, and whether the start of the channel was reached. Expects the channel to be tracked. */
+    pub async fn update_channel_backfill(
+        &self,
+        channel_id: u64,
+        oldest_message_id: Option<u64>,
+        complete: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO channel_backfills (channel_id, oldest_message_id, complete) VALUES ($1, $2, $3)
+             ON CONFLICT (channel_id) DO UPDATE SET oldest_message_id = EXCLUDED.oldest_message_id, complete = EXCLUDED.complete;",
+            channel_id as i64,
+            oldest_message_id.map(|id| id as i64),
+            complete
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Returns the webhook the bot made in a channel, if there is one. */
+    pub async fn fetch_channel_webhook(
+        &self,
+        channel_id: u64,
+    ) -> Result<Option<entities::ChannelWebhook>, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT webhook_id, token FROM channel_webhooks WHERE channel_id = $1;",
+            channel_id as i64
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(record.map(|record| entities::ChannelWebhook {
+            webhook_id: record.webhook_id as u64,
+            token: record.token,
+        }))
+    }
+
+    /** Records the webhook the bot made in a channel, replacing any previous one. Expects the channel to be tracked. */
+    pub async fn set_channel_webhook(
+        &self,
+        channel_id: u64,
+        webhook: &entities::ChannelWebhook,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO channel_webhooks (channel_id, webhook_id, token) VALUES ($1, $2, $3)
+             ON CONFLICT (channel_id) DO UPDATE SET webhook_id = EXCLUDED.webhook_id, token = EXCLUDED.token;",
+            channel_id as i64,
+            webhook.webhook_id as i64,
+            webhook.token
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Forgets the webhook the bot made in a channel, returning it so it can be deleted from discord too. */
+    pub async fn remove_channel_webhook(
+        &self,
+        channel_id: u64,
+    ) -> Result<Option<entities::ChannelWebhook>, sqlx::Error> {
+        let record = sqlx::query!(
+            "DELETE FROM channel_webhooks WHERE channel_id = $1 RETURNING webhook_id, token;",
+            channel_id as i64
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(record.map(|record| entities::ChannelWebhook {
+            webhook_id: record.webhook_id as u64,
+            token: record.token,
+        }))
+    }
+
+    /** Records that a player won a round of "Who said it?" in a server, returning how many rounds they have won there now. */
+    pub async fn add_game_win(&self, server_id: u64, user_id: u64) -> Result<u32, sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO servers(server_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        let record = sqlx::query!(
+            "INSERT INTO game_scores (server_id, user_id, wins) VALUES ($1, $2, 1)
+             ON CONFLICT (server_id, user_id) DO UPDATE SET wins = game_scores.wins + 1
+             RETURNING wins;",
+            server_id as i64,
+            user_id as i64
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(record.wins as u32)
+    }
+
+    /** Returns the players with the most "Who said it?" wins in a server, best first. Ties go to whoever has the lowest user_id, so the order is stable. */
+    pub async fn fetch_leaderboard(
+        &self,
+        server_id: u64,
+        limit: u32,
+    ) -> Result<Vec<entities::GameScore>, sqlx::Error> {
+        Ok(sqlx::query!(
+            "SELECT user_id, wins FROM game_scores WHERE server_id = $1 ORDER BY wins DESC, user_id LIMIT $2;",
+            server_id as i64,
+            limit as i64
+        )
+        .fetch_all(&self.db)
         .await?
-        {
-            word_ids.push(word_id.word_id as i32);
-            word_ids.push(word_id.next_word_id as i32);
+        .into_iter()
+        .map(|record| entities::GameScore {
+            user_id: record.user_id as u64,
+            wins: record.wins as u32,
+        })
+        .collect())
+    }
+
+    /** Wipes the "Who said it?" leaderboard of a server. */
+    pub async fn reset_leaderboard(
+        &self,
+        server_id: u64,
+    ) -> Result<errors::RemoveResult, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM game_scores WHERE server_id = $1",
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        if result.rows_affected() > 0 {
+            Ok(errors::RemoveResult::Removed)
         } else {
-            return Err(errors::MarkovFetchResultError::NotEnoughWords(
-                desired_sentence_length,
-                0,
-            ));
-        }
-        for i in 0..desired_sentence_length - 2 {
-            let current_word_id = &word_ids[i as usize];
-            let next_word = sqlx::query!(
-                "SELECT next_word_id FROM member_words WHERE member_id = $1 AND frequency > random()*$2 AND word_id = $3 ORDER BY random() LIMIT 1;",
-                member_id as i32,
-                freq_sum,
-                *current_word_id as i32
-            ).fetch_optional(&self.db).await?;
-            if let Some(word) = next_word {
-                word_ids.push(word.next_word_id as i32)
-            } else {
-                return Err(errors::MarkovFetchResultError::NotEnoughWords(
-                    desired_sentence_length,
-                    u32::try_from(word_ids.len())
-                        .expect("Should be able to convert vector length to u32 size"),
-                ));
-            }
+            Ok(errors::RemoveResult::NotPresent)
         }
+    }
 
-        // Now get actual words from word_ids:
-        match sqlx::query!(
-            "SELECT word FROM words WHERE word_id = ANY($1)",
-            &word_ids[..]
+    /** Returns the server a personality belongs to, if it is known. A set of members belongs to the server of its first member. */
+    async fn fetch_personality_server(
+        &self,
+        personality: &entities::Personality,
+    ) -> Result<Option<u64>, sqlx::Error> {
+        Ok(match personality {
+            entities::Personality::Members(members) => match members.first() {
+                Some((member_id, _)) => sqlx::query!(
+                    "SELECT server_id FROM members WHERE member_id = $1;",
+                    *member_id as i32
+                )
+                .fetch_optional(&self.db)
+                .await?
+                .map(|record| record.server_id as u64),
+                None => None,
+            },
+            entities::Personality::Channel(channel_id) => sqlx::query!(
+                "SELECT server_id FROM channels WHERE channel_id = $1;",
+                *channel_id as i64
+            )
+            .fetch_optional(&self.db)
+            .await?
+            .map(|record| record.server_id as u64),
+            entities::Personality::Server(server_id) => Some(*server_id),
+        })
+    }
+
+    /** Returns the word_id of every word in the same order, or nothing if any of the words is unknown. Expects the keys words are stored by, as returned by `textprocessing::normalize`. */
+    pub async fn fetch_word_ids(&self, words: &[String]) -> Result<Option<Vec<i32>>, sqlx::Error> {
+        let found_words: HashMap<String, i32> = sqlx::query!(
+            "SELECT word_id, word FROM words WHERE word = ANY($1)",
+            words
         )
         .fetch_all(&self.db)
-        .await
-        {
-            Ok(word_vec) => Ok(word_vec.into_iter().map(|record| record.word).join(" ")),
-            Err(why) => Err(errors::MarkovFetchResultError::SqlxError(why)),
+        .await?
+        .into_iter()
+        .map(|record| (record.word, record.word_id))
+        .collect();
+        Ok(words
+            .iter()
+            .map(|word| found_words.get(word).copied())
+            .collect())
+    }
+
+    /** Returns the words for a sequence of word_ids along with their most common surface forms, in the same order and including repeats. */
+    pub async fn fetch_words(
+        &self,
+        word_ids: &[i32],
+    ) -> Result<Vec<textprocessing::SurfaceForms>, sqlx::Error> {
+        let records = sqlx::query!(
+            r#"SELECT words.word AS "word!",
+             (SELECT form FROM word_forms WHERE word_forms.word_id = words.word_id AND sentence_start ORDER BY frequency DESC, form LIMIT 1) AS sentence_start_form,
+             (SELECT form FROM word_forms WHERE word_forms.word_id = words.word_id AND NOT sentence_start ORDER BY frequency DESC, form LIMIT 1) AS elsewhere_form
+             FROM UNNEST($1::INTEGER[]) WITH ORDINALITY AS sequence(word_id, position)
+             INNER JOIN words ON words.word_id = sequence.word_id ORDER BY sequence.position;"#,
+            word_ids
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(records
+            .into_iter()
+            .map(|record| textprocessing::SurfaceForms {
+                key: record.word,
+                sentence_start: record.sentence_start_form,
+                elsewhere: record.elsewhere_form,
+            })
+            .collect())
+    }
+
+    /** Returns the word_ids of the begin and end of message tokens, if any message has been learned from yet. */
+    async fn fetch_sentinel_word_ids(&self) -> Result<Option<(i32, i32)>, sqlx::Error> {
+        if let Some(sentinel_word_ids) = self.lock_cache().sentinel_word_ids {
+            return Ok(Some(sentinel_word_ids));
+        }
+        let records = sqlx::query!(
+            "SELECT word_id, word FROM words WHERE word = $1 OR word = $2;",
+            textprocessing::BEGIN_OF_MESSAGE,
+            textprocessing::END_OF_MESSAGE
+        )
+        .fetch_all(&self.db)
+        .await?;
+        let find_word_id = |sentinel: &str| {
+            records
+                .iter()
+                .find(|record| record.word == sentinel)
+                .map(|record| record.word_id)
+        };
+        let sentinel_word_ids = find_word_id(textprocessing::BEGIN_OF_MESSAGE)
+            .zip(find_word_id(textprocessing::END_OF_MESSAGE));
+        // They never change once they are known
+        self.lock_cache().sentinel_word_ids = sentinel_word_ids;
+        Ok(sentinel_word_ids)
+    }
+
+    /** Returns every word a personality said right before a context, along with how often it did. A word came before the context when the word followed by all but the last word of the context was followed by the last word. */
+    async fn fetch_predecessors(
+        &self,
+        personality: &entities::Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, sqlx::Error> {
+        let (last_word_id, rest) = context
+            .split_last()
+            .expect("Should have a context to look before");
+        match personality {
+            entities::Personality::Members(members) => {
+                self.fetch_member_predecessors(members, context).await
+            }
+            entities::Personality::Channel(channel_id) => Ok(sqlx::query!(
+                r#"SELECT context[1] AS "previous_word_id!", frequency FROM channel_ngrams
+                 WHERE channel_id = $1 AND next_word_id = $2 AND context[2:] = $3;"#,
+                *channel_id as i64,
+                last_word_id,
+                rest
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|record| (record.previous_word_id, record.frequency as f64))
+            .collect()),
+            entities::Personality::Server(server_id) => Ok(sqlx::query!(
+                r#"SELECT context[1] AS "previous_word_id!", SUM(frequency)::BIGINT AS "frequency!" FROM channel_ngrams
+                 INNER JOIN channels ON channel_ngrams.channel_id = channels.channel_id
+                 WHERE server_id = $1 AND next_word_id = $2 AND context[2:] = $3 GROUP BY context[1];"#,
+                *server_id as i64,
+                last_word_id,
+                rest
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|record| (record.previous_word_id, record.frequency as f64))
+            .collect()),
+        }
+    }
+
+    /** Returns every word a personality followed a context with, along with how often it did. */
+    async fn fetch_successors(
+        &self,
+        personality: &entities::Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, sqlx::Error> {
+        match personality {
+            entities::Personality::Members(members) => {
+                self.fetch_member_successors(members, context).await
+            }
+            entities::Personality::Channel(channel_id) => Ok(sqlx::query!(
+                "SELECT next_word_id, frequency FROM channel_ngrams WHERE channel_id = $1 AND context = $2;",
+                *channel_id as i64,
+                context
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|record| (record.next_word_id, record.frequency as f64))
+            .collect()),
+            entities::Personality::Server(server_id) => Ok(sqlx::query!(
+                r#"SELECT next_word_id, SUM(frequency)::BIGINT AS "frequency!" FROM channel_ngrams
+                 INNER JOIN channels ON channel_ngrams.channel_id = channels.channel_id
+                 WHERE server_id = $1 AND context = $2 GROUP BY next_word_id;"#,
+                *server_id as i64,
+                context
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|record| (record.next_word_id, record.frequency as f64))
+            .collect()),
+        }
+    }
+
+    /** Returns every word a set of members followed a context with, along with how often they did, scaled by the weight of each member. */
+    async fn fetch_member_successors(
+        &self,
+        members: &[(u32, f64)],
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, sqlx::Error> {
+        let mut records: Vec<(i32, i32, i64)> = vec![];
+        for (member_id, _) in members {
+            let successors = self
+                .lookup_member_model(*member_id, |model| model.successors(context))
+                .await?;
+            records.extend(successors.into_iter().map(|(next_word_id, frequency)| {
+                (*member_id as i32, next_word_id, frequency as i64)
+            }));
+        }
+        Ok(weigh_member_frequencies(members, records))
+    }
+
+    /** Returns every word a set of members said right before a context, along with how often they did, scaled by the weight of each member. */
+    async fn fetch_member_predecessors(
+        &self,
+        members: &[(u32, f64)],
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, sqlx::Error> {
+        let mut records: Vec<(i32, i32, i64)> = vec![];
+        for (member_id, _) in members {
+            let predecessors = self
+                .lookup_member_model(*member_id, |model| model.predecessors(context))
+                .await?;
+            records.extend(
+                predecessors
+                    .into_iter()
+                    .map(|(previous_word_id, frequency)| {
+                        (*member_id as i32, previous_word_id, frequency as i64)
+                    }),
+            );
+        }
+        Ok(weigh_member_frequencies(members, records))
+    }
+
+    /** Looks something up in the cached model of a member, loading it first if it isn't cached. */
+    async fn lookup_member_model<T>(
+        &self,
+        member_id: u32,
+        lookup: impl Fn(&TransitionModel) -> T,
+    ) -> Result<T, sqlx::Error> {
+        loop {
+            self.load_member_model(member_id).await?;
+            // Another task may have pushed the model out of the cache again in the meantime
+            if let Some(model) = self.lock_cache().get(member_id) {
+                return Ok(lookup(model));
+            }
         }
     }
 
-    /** Adds a vector of words to known words. Expects words to be pre-processed. */
+    /** Loads every transition of a member into the model cache, unless they are cached already. Loads them again if a message of the member was learned in the meantime. */
+    async fn load_member_model(&self, member_id: u32) -> Result<(), sqlx::Error> {
+        loop {
+            let learned_before_loading = {
+                let mut cache = self.lock_cache();
+                if cache.get(member_id).is_some() {
+                    return Ok(());
+                }
+                cache.learned(member_id)
+            };
+            let mut model = TransitionModel::default();
+            for record in sqlx::query!(
+                "SELECT word_id, next_word_id, frequency FROM member_words WHERE member_id = $1;",
+                member_id as i32
+            )
+            .fetch_all(&self.db)
+            .await?
+            {
+                model.add_transition(
+                    &[record.word_id],
+                    record.next_word_id,
+                    record.frequency as u64,
+                );
+            }
+            for record in sqlx::query!(
+                "SELECT context, next_word_id, frequency FROM member_ngrams WHERE member_id = $1;",
+                member_id as i32
+            )
+            .fetch_all(&self.db)
+            .await?
+            {
+                model.add_transition(
+                    &record.context,
+                    record.next_word_id,
+                    record.frequency as u64,
+                );
+            }
+            if self
+                .lock_cache()
+                .insert(member_id, model, learned_before_loading)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, ModelCache> {
+        self.cache
+            .lock()
+            .expect("Should be able to lock the model cache")
+    }
+
+    /** Returns the number of words of context used to generate text that sounds like a personality, which is the order picked for its server. */
+    async fn fetch_personality_markov_order(
+        &self,
+        personality: &entities::Personality,
+    ) -> Result<u8, sqlx::Error> {
+        match self.fetch_personality_server(personality).await? {
+            Some(server_id) => self.fetch_markov_order(server_id).await,
+            None => Ok(textprocessing::DEFAULT_MARKOV_ORDER),
+        }
+    }
+
+    /** Returns how much has been learned about a personality, counted from the transitions out of single words. */
+    pub async fn fetch_stats(
+        &self,
+        personality: &entities::Personality,
+    ) -> Result<ModelStats, sqlx::Error> {
+        let begin_word_id = match self.fetch_sentinel_word_ids().await? {
+            Some((begin_word_id, _)) => begin_word_id,
+            None => return Ok(ModelStats::default()),
+        };
+        let (messages, words, transitions) = match personality {
+            entities::Personality::Members(members) => {
+                let member_ids: Vec<i32> = members
+                    .iter()
+                    .map(|(member_id, _)| *member_id as i32)
+                    .collect();
+                let record = sqlx::query!(
+                    r#"SELECT COALESCE(SUM(frequency) FILTER (WHERE word_id = $2), 0)::BIGINT AS "messages!",
+                     COUNT(DISTINCT word_id) FILTER (WHERE word_id <> $2) AS "words!",
+                     COALESCE(SUM(frequency), 0)::BIGINT AS "transitions!"
+                     FROM member_words WHERE member_id = ANY($1);"#,
+                    &member_ids[..],
+                    begin_word_id
+                )
+                .fetch_one(&self.db)
+                .await?;
+                (record.messages, record.words, record.transitions)
+            }
+            entities::Personality::Channel(channel_id) => {
+                let record = sqlx::query!(
+                    r#"SELECT COALESCE(SUM(frequency) FILTER (WHERE context[1] = $2), 0)::BIGINT AS "messages!",
+                     COUNT(DISTINCT context[1]) FILTER (WHERE context[1] <> $2) AS "words!",
+                     COALESCE(SUM(frequency), 0)::BIGINT AS "transitions!"
+                     FROM channel_ngrams WHERE channel_id = $1 AND cardinality(context) = 1;"#,
+                    *channel_id as i64,
+                    begin_word_id
+                )
+                .fetch_one(&self.db)
+                .await?;
+                (record.messages, record.words, record.transitions)
+            }
+            entities::Personality::Server(server_id) => {
+                let record = sqlx::query!(
+                    r#"SELECT COALESCE(SUM(frequency) FILTER (WHERE context[1] = $2), 0)::BIGINT AS "messages!",
+                     COUNT(DISTINCT context[1]) FILTER (WHERE context[1] <> $2) AS "words!",
+                     COALESCE(SUM(frequency), 0)::BIGINT AS "transitions!"
+                     FROM channel_ngrams INNER JOIN channels ON channel_ngrams.channel_id = channels.channel_id
+                     WHERE server_id = $1 AND cardinality(context) = 1;"#,
+                    *server_id as i64,
+                    begin_word_id
+                )
+                .fetch_one(&self.db)
+                .await?;
+                (record.messages, record.words, record.transitions)
+            }
+        };
+        Ok(ModelStats {
+            messages: messages as u64,
+            words: words as u64,
+            transitions: transitions as u64,
+        })
+    }
+
+    /** Returns the largest share of a generated sentence that may match messages learned in a server. */
+    pub async fn fetch_originality_threshold(&self, server_id: u64) -> Result<f64, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT originality_threshold FROM server_settings WHERE server_id = $1;",
+            server_id as i64
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(record
+            .map(|record| record.originality_threshold)
+            .unwrap_or(originality::DEFAULT_ORIGINALITY_THRESHOLD))
+    }
+
+    /** Sets the largest share of a generated sentence that may match messages learned in a server. Expects the threshold to be between 0 and 1. */
+    pub async fn set_originality_threshold(
+        &self,
+        server_id: u64,
+        threshold: f64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO servers(server_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO server_settings (server_id, originality_threshold) VALUES ($1, $2)
+             ON CONFLICT (server_id) DO UPDATE SET originality_threshold = EXCLUDED.originality_threshold;",
+            server_id as i64,
+            threshold
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Returns the number of words of context used to generate text in a server. */
+    pub async fn fetch_markov_order(&self, server_id: u64) -> Result<u8, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT markov_order FROM server_settings WHERE server_id = $1;",
+            server_id as i64
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(record
+            .map(|record| record.markov_order as u8)
+            .unwrap_or(textprocessing::DEFAULT_MARKOV_ORDER))
+    }
+
+    /** Sets the number of words of context used to generate text in a server. Expects the order to be between 1 and `MAX_MARKOV_ORDER`. */
+    pub async fn set_markov_order(
+        &self,
+        server_id: u64,
+        markov_order: u8,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO servers(server_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO server_settings (server_id, markov_order) VALUES ($1, $2)
+             ON CONFLICT (server_id) DO UPDATE SET markov_order = EXCLUDED.markov_order;",
+            server_id as i64,
+            markov_order as i16
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Returns who the bot talks like when it is mentioned or replied to in a server. Servers that haven't picked a persona, or whose member persona no longer exists, get the channel. */
+    pub async fn fetch_reply_persona(
+        &self,
+        server_id: u64,
+    ) -> Result<entities::ReplyPersona, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT reply_persona, reply_member_id FROM server_settings WHERE server_id = $1;",
+            server_id as i64
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(match record {
+            Some(record) => match (record.reply_persona.as_str(), record.reply_member_id) {
+                ("member", Some(member_id)) => entities::ReplyPersona::Member(member_id as u32),
+                ("server", _) => entities::ReplyPersona::Server,
+                _ => entities::ReplyPersona::Channel,
+            },
+            None => entities::ReplyPersona::Channel,
+        })
+    }
+
+    /** Sets who the bot talks like when it is mentioned or replied to in a server. */
+    pub async fn set_reply_persona(
+        &self,
+        server_id: u64,
+        persona: entities::ReplyPersona,
+    ) -> Result<(), sqlx::Error> {
+        let (reply_persona, reply_member_id) = match persona {
+            entities::ReplyPersona::Member(member_id) => ("member", Some(member_id as i32)),
+            entities::ReplyPersona::Channel => ("channel", None),
+            entities::ReplyPersona::Server => ("server", None),
+        };
+        sqlx::query!(
+            "INSERT INTO servers(server_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            server_id as i64
+        )
+        .execute(&self.db)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO server_settings (server_id, reply_persona, reply_member_id) VALUES ($1, $2, $3)
+             ON CONFLICT (server_id) DO UPDATE SET reply_persona = EXCLUDED.reply_persona, reply_member_id = EXCLUDED.reply_member_id;",
+            server_id as i64,
+            reply_persona,
+            reply_member_id
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Adds a vector of words to known words. Expects words to be pre-processed. Returns the word_id of every word in the same order, including repeated words. */
     pub async fn add_words(&self, words: Vec<String>) -> Result<Vec<i32>, sqlx::Error> {
-        let results = sqlx::query!(
-            "INSERT INTO words(word) SELECT * FROM UNNEST($1::text[]) ON CONFLICT DO NOTHING RETURNING word_id",
+        let inserted = sqlx::query!(
+            "INSERT INTO words(word) SELECT * FROM UNNEST($1::text[]) ON CONFLICT DO NOTHING RETURNING word_id, word",
             &words[..]
         )
         .fetch_all(&self.db.clone())
         .await?;
-        if results.len() != words.len() {
-            // If we don't insert all the words, our query is empty; therefore we need to select the data and map it to the words to get the right order
-            let found_words = sqlx::query!(
+        // The insert returns its rows in no particular order, so every word_id is mapped to its word to get the right order
+        let mut found_words: HashMap<String, i32> = HashMap::from_iter(
+            inserted
+                .into_iter()
+                .map(|record| (record.word, record.word_id)),
+        );
+        // Words that were already known aren't returned by the insert, so they are selected
+        if words.iter().any(|word| !found_words.contains_key(word)) {
+            let known_words = sqlx::query!(
                 "SELECT word_id, word FROM words WHERE word = ANY($1)",
                 &words[..]
             )
             .fetch_all(&self.db)
             .await?;
-            // Now make it into a dictionary for easy access to the word_id values:
-            let mut found_words = HashMap::from_iter(
-                found_words
+            found_words.extend(
+                known_words
                     .into_iter()
                     .map(|record| (record.word, record.word_id)),
             );
-            let mut returnable = vec![];
-            for word in words {
-                returnable.push(found_words.get(&word).unwrap_or(&0)); // 0 for failure
-            }
         }
-        Ok(results.into_iter().map(|f| f.word_id).collect_vec())
+        words
+            .iter()
+            .map(|word| {
+                found_words
+                    .get(word)
+                    .copied()
+                    .ok_or(sqlx::Error::RowNotFound)
+            })
+            .collect()
     }
 
-    // Adds member words
-    pub async fn add_member_words(
+    /** Counts every transition in a sequence of word_ids towards a member. */
+    pub async fn add_member_word_ids(
         &self,
         member_id: u32,
-        member_words: Vec<String>,
-    ) -> Result<(), errors::AddMemberWordsError> {
-        let member_word_ids = self.add_words(member_words).await?;
-        println!("member_word_ids: {:?}", member_word_ids);
-        let word_pairs = textprocessing::vec_word_split(member_word_ids);
-        println!("word_pairs: {:?}", word_pairs);
+        member_word_ids: &[i32],
+    ) -> Result<(), sqlx::Error> {
+        // Keeps a model loaded while the transitions are written from being cached, since it may already have some of them
+        let cached_at_start = self.lock_cache().start_learning(member_id);
+        let word_pairs = textprocessing::vec_word_split(member_word_ids.to_vec(), 1);
         for insertable in word_pairs {
             sqlx::query!("INSERT INTO member_words (word_id, member_id, next_word_id, frequency) VALUES ($1, $2, $3, 1)
              ON CONFLICT (word_id, member_id, next_word_id) DO UPDATE SET frequency = member_words.frequency + 1;",
             &insertable[0],
             member_id as i32,
             &insertable[1]).execute(&self.db).await?;
-            println!("word_id: {:?}", &insertable[0]);
-            println!("member_id: {:?}", member_id);
-            println!("next_word_id: {:?}", &insertable[1]);
+        }
+        // Longer contexts are learned for every order, so a server can change the order it generates with at any time
+        for order in 2..=textprocessing::MAX_MARKOV_ORDER as usize {
+            for insertable in textprocessing::vec_word_split(member_word_ids.to_vec(), order) {
+                sqlx::query!("INSERT INTO member_ngrams (member_id, context, next_word_id, frequency) VALUES ($1, $2, $3, 1)
+                 ON CONFLICT (member_id, context, next_word_id) DO UPDATE SET frequency = member_ngrams.frequency + 1;",
+                member_id as i32,
+                &insertable[..order],
+                insertable[order]).execute(&self.db).await?;
+            }
+        }
+        self.lock_cache()
+            .learn(member_id, member_word_ids, cached_at_start);
+        Ok(())
+    }
+
+    /** Remembers the hashes of every run of words in a message, including the begin and end of message tokens, in the server of the member who sent it. */
+    async fn add_message_shingles(
+        &self,
+        member_id: u32,
+        word_ids: &[i32],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO message_shingles (server_id, shingle_hash)
+             SELECT members.server_id, shingles.shingle_hash FROM members, UNNEST($2::BIGINT[]) AS shingles(shingle_hash)
+             WHERE members.member_id = $1 ON CONFLICT DO NOTHING;",
+            member_id as i32,
+            &originality::shingle_hashes(word_ids)[..]
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Counts how every token of a learned message was typed, so generated text can be typed the same way. Expects the word_ids of the tokens, in the same order. The begin and end of message tokens are never typed, so they are skipped. */
+    async fn add_word_forms(
+        &self,
+        word_ids: &[i32],
+        tokens: &[textprocessing::Token],
+    ) -> Result<(), sqlx::Error> {
+        // Forms typed more than once in a message are added up first, since a single insert can't update the same row twice
+        let mut frequencies: HashMap<(i32, &str, bool), i64> = HashMap::new();
+        for (word_id, token) in word_ids.iter().zip(&tokens[..tokens.len() - 1]).skip(1) {
+            *frequencies
+                .entry((*word_id, token.form.as_str(), token.sentence_start))
+                .or_insert(0) += 1;
+        }
+        let mut form_word_ids = vec![];
+        let mut forms = vec![];
+        let mut sentence_starts = vec![];
+        let mut form_frequencies = vec![];
+        for ((word_id, form, sentence_start), frequency) in frequencies {
+            form_word_ids.push(word_id);
+            forms.push(form.to_owned());
+            sentence_starts.push(sentence_start);
+            form_frequencies.push(frequency);
+        }
+        sqlx::query!(
+            "INSERT INTO word_forms (word_id, form, sentence_start, frequency)
+             SELECT * FROM UNNEST($1::INTEGER[], $2::TEXT[], $3::BOOLEAN[], $4::BIGINT[])
+             ON CONFLICT (word_id, form, sentence_start) DO UPDATE SET frequency = word_forms.frequency + EXCLUDED.frequency;",
+            &form_word_ids[..],
+            &forms[..],
+            &sentence_starts[..],
+            &form_frequencies[..]
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /** Counts every transition in a sequence of word_ids towards the channel it was sent in. Expects the channel to be known. */
+    async fn add_channel_word_ids(
+        &self,
+        channel_id: u64,
+        word_ids: &[i32],
+    ) -> Result<(), sqlx::Error> {
+        for order in 1..=textprocessing::MAX_MARKOV_ORDER as usize {
+            for insertable in textprocessing::vec_word_split(word_ids.to_vec(), order) {
+                sqlx::query!("INSERT INTO channel_ngrams (channel_id, context, next_word_id, frequency) VALUES ($1, $2, $3, 1)
+                 ON CONFLICT (channel_id, context, next_word_id) DO UPDATE SET frequency = channel_ngrams.frequency + 1;",
+                channel_id as i64,
+                &insertable[..order],
+                insertable[order]).execute(&self.db).await?;
+            }
         }
         Ok(())
     }
 
     /** deletes all data from all tables. For testing only, Do not use on production. */
     pub async fn reinit_all_data(&self) -> Result<bool, sqlx::Error> {
-        //Don't want to have to split this up into multiple queries, but I guess I have to...
         sqlx::query!("DROP SCHEMA public CASCADE;")
             .execute(&self.db.clone())
             .await?;
@@ -511,77 +1233,148 @@ impl DbInterface {
         sqlx::query!("GRANT ALL ON SCHEMA public TO postgres;")
             .execute(&self.db.clone())
             .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS servers (
-            server_id BIGINT NOT NULL,
-            PRIMARY KEY (server_id)
-        );"
-        )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS users (
-            user_id BIGINT NOT NULL,
-            PRIMARY KEY (user_id)
-        );"
-        )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS members (
-            member_id SERIAL NOT NULL,
-            user_id BIGINT NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
-            server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
-            PRIMARY KEY (member_id)
-        );"
-        )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS channels (
-            channel_id BIGINT NOT NULL,
-            server_id BIGINT NOT NULL REFERENCES servers (server_id) ON DELETE CASCADE,
-            PRIMARY KEY (channel_id)
-        );"
-        )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS tracked_channels (
-            channel_id BIGINT NOT NULL REFERENCES channels (channel_id) ON DELETE CASCADE
-        );"
-        )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS tracked_members (
-            member_id INTEGER NOT NULL REFERENCES members (member_id) ON DELETE CASCADE
-        );"
-        )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS words (
-            word_id SERIAL NOT NULL,
-            word TEXT NOT NULL,
-            PRIMARY KEY (word_id),
-            UNIQUE (word)    
-        );"
+        // The migration history was dropped along with the schema, so every migration is applied again
+        self.migrate().await?;
+        // Every cached model and word_id is gone too
+        *self.lock_cache() = ModelCache::new(cache::MODEL_CACHE_BUDGET_BYTES);
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl TransitionSource for DbInterface {
+    async fn sentinel_word_ids(
+        &self,
+    ) -> Result<Option<(i32, i32)>, errors::MarkovFetchResultError> {
+        Ok(self.fetch_sentinel_word_ids().await?)
+    }
+
+    async fn word_ids(
+        &self,
+        words: &[String],
+    ) -> Result<Option<Vec<i32>>, errors::MarkovFetchResultError> {
+        Ok(self.fetch_word_ids(words).await?)
+    }
+
+    async fn words(
+        &self,
+        word_ids: &[i32],
+    ) -> Result<Vec<textprocessing::SurfaceForms>, errors::MarkovFetchResultError> {
+        Ok(self.fetch_words(word_ids).await?)
+    }
+
+    async fn successors(
+        &self,
+        personality: &entities::Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, errors::MarkovFetchResultError> {
+        Ok(self.fetch_successors(personality, context).await?)
+    }
+
+    async fn predecessors(
+        &self,
+        personality: &entities::Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, errors::MarkovFetchResultError> {
+        Ok(self.fetch_predecessors(personality, context).await?)
+    }
+
+    async fn originality_threshold(
+        &self,
+        personality: &entities::Personality,
+    ) -> Result<Option<f64>, errors::MarkovFetchResultError> {
+        Ok(match self.fetch_personality_server(personality).await? {
+            Some(server_id) => Some(self.fetch_originality_threshold(server_id).await?),
+            None => None,
+        })
+    }
+
+    async fn known_shingles(
+        &self,
+        personality: &entities::Personality,
+        shingle_hashes: &[i64],
+    ) -> Result<HashSet<i64>, errors::MarkovFetchResultError> {
+        let server_id = match self.fetch_personality_server(personality).await? {
+            Some(server_id) => server_id,
+            None => return Ok(HashSet::new()),
+        };
+        Ok(sqlx::query!(
+            "SELECT DISTINCT shingle_hash FROM message_shingles WHERE server_id = $1 AND shingle_hash = ANY($2);",
+            server_id as i64,
+            shingle_hashes
         )
-        .execute(&self.db.clone())
-        .await?;
-        sqlx::query!(
-            "CREATE TABLE IF NOT EXISTS member_words (
-            word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
-            member_id INTEGER NOT NULL REFERENCES members (member_id) ON DELETE CASCADE,
-            next_word_id INTEGER NOT NULL REFERENCES words (word_id) ON DELETE CASCADE,
-            frequency BIGINT NOT NULL,
-            PRIMARY KEY (word_id, member_id, next_word_id)
-        );"
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|record| record.shingle_hash)
+        .collect())
+    }
+}
+
+/** Text is learned into and generated from the tables in Postgres, with the transitions of recently used members cached in memory. */
+#[async_trait]
+impl TextModel for DbInterface {
+    async fn learn(
+        &self,
+        member_id: u32,
+        channel_id: Option<u64>,
+        content: &str,
+    ) -> Result<bool, errors::AddMemberWordsError> {
+        self.learn_member_message(member_id, channel_id, content)
+            .await
+    }
+
+    async fn generate(
+        &self,
+        personality: &entities::Personality,
+        temperature: f64,
+        seed: Option<&str>,
+        seed_position: entities::SeedPosition,
+    ) -> Result<String, errors::MarkovFetchResultError> {
+        let markov_order = self.fetch_personality_markov_order(personality).await?;
+        markov::generate_sentence(
+            self,
+            personality,
+            markov_order,
+            temperature,
+            seed,
+            seed_position,
         )
-        .execute(&self.db.clone())
-        .await?;
+        .await
+    }
 
-        Ok(true)
+    async fn generate_reply(
+        &self,
+        personality: &entities::Personality,
+        temperature: f64,
+        seeds: &[String],
+    ) -> Result<String, errors::MarkovFetchResultError> {
+        let markov_order = self.fetch_personality_markov_order(personality).await?;
+        markov::generate_reply_sentence(self, personality, markov_order, temperature, seeds).await
+    }
+
+    async fn stats(
+        &self,
+        personality: &entities::Personality,
+    ) -> Result<ModelStats, errors::MarkovFetchResultError> {
+        Ok(self.fetch_stats(personality).await?)
+    }
+}
+
+/** Adds up `(member_id, word_id, frequency)` records into `(word_id, frequency)` pairs, scaling each member's frequencies by their weight. */
+fn weigh_member_frequencies(
+    members: &[(u32, f64)],
+    records: Vec<(i32, i32, i64)>,
+) -> Vec<(i32, f64)> {
+    let weights: HashMap<i32, f64> = members
+        .iter()
+        .map(|(member_id, weight)| (*member_id as i32, *weight))
+        .collect();
+    let mut frequencies: HashMap<i32, f64> = HashMap::new();
+    for (member_id, word_id, frequency) in records {
+        *frequencies.entry(word_id).or_insert(0.0) +=
+            frequency as f64 * weights.get(&member_id).copied().unwrap_or(0.0);
     }
+    frequencies.into_iter().collect()
 }
@@ -3,7 +3,30 @@ mod tests {
 
     use tokio::time::sleep;
 
+    use crate::client::database::entities;
+    use crate::client::database::errors;
     use crate::client::database::interface::*;
+    use crate::client::textgen::markov;
+    use crate::client::textgen::textprocessing::SurfaceForms;
+
+    /** Learns from a message the way the message event does, expecting the member and channel to be tracked. */
+    async fn learn_message(
+        db_int: &DbInterface,
+        server_id: u64,
+        channel_id: u64,
+        user_id: u64,
+        content: &str,
+    ) {
+        let member_id = db_int
+            .fetch_learning_member(server_id, channel_id, user_id)
+            .await
+            .expect("Should be able to check message")
+            .expect("Should learn from a tracked member in a tracked channel");
+        db_int
+            .learn_member_message(member_id, Some(channel_id), content)
+            .await
+            .expect("Should be able to learn from message");
+    }
 
     #[tokio::test]
     async fn test_fetch_tracked_user_members() {
@@ -369,6 +392,35 @@ mod tests {
         assert!(true);
     }
 
+    #[tokio::test]
+    async fn test_add_words_returns_word_ids_in_order() {
+        // Tests that every word gets its own word_id back in input order, whether it was already present, new or repeated
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        let first_word_ids = db_int
+            .add_words(vec!["test1".to_string(), "test2".to_string()])
+            .await
+            .expect("Added first words");
+        let second_word_ids = db_int
+            .add_words(vec![
+                "test3".to_string(),
+                "test2".to_string(),
+                "test3".to_string(),
+                "test1".to_string(),
+            ])
+            .await
+            .expect("Added second words");
+        assert_eq!(4, second_word_ids.len());
+        assert_eq!(second_word_ids[0], second_word_ids[2]);
+        assert_eq!(first_word_ids[1], second_word_ids[1]);
+        assert_eq!(first_word_ids[0], second_word_ids[3]);
+        assert!(!first_word_ids.contains(&second_word_ids[0]));
+        db_int.reinit_all_data().await.expect("reinit");
+    }
+
     #[tokio::test]
     async fn test_add_member_words_no_words_present() {
         use std::time::Duration;
@@ -387,8 +439,12 @@ mod tests {
             .await
             .expect("Should get a result")
             .expect("Should have a member here");
+        let word_ids = db_int
+            .add_words(test_words)
+            .await
+            .expect("Should be able to add words here");
         db_int
-            .add_member_words(member_id, test_words)
+            .add_member_word_ids(member_id, &word_ids)
             .await
             .expect("Should be able to add member words here");
         let expected_row1 = (1, member_id, 2, 1);
@@ -407,7 +463,6 @@ mod tests {
         .fetch_one(&db_int.db)
         .await
         .expect("Shoudl be able to get row");
-        //Assertion fails here!
         assert_eq!(
             expected_row1,
             (
@@ -457,15 +512,23 @@ mod tests {
             .expect("Should have a member here");
         let test_words_clone = test_words.clone();
 
+        let word_ids = db_int
+            .add_words(test_words)
+            .await
+            .expect("Should be able to add words here");
         db_int
-            .add_member_words(member_id, test_words)
+            .add_member_word_ids(member_id, &word_ids)
             .await
             .expect("Should be able to add member words here"); // Adding words in an empty db works fine
                                                                 // No errors here
 
         sleep(Duration::from_secs(1)).await; // If this is necessary, the program becomes *really* unstable at runtime.
+        let word_ids = db_int
+            .add_words(test_words_clone)
+            .await
+            .expect("Should be able to add words here");
         db_int
-            .add_member_words(member_id, test_words_clone)
+            .add_member_word_ids(member_id, &word_ids)
             .await
             .expect("Should be able to add member words here");
 
@@ -487,7 +550,6 @@ mod tests {
         .fetch_one(&db_int.db)
         .await
         .expect("Shoudl be able to get row");
-        // Assertion fails here! found_row1.frequency == 1, not the expected 2
         assert_eq!(
             expected_row1,
             (
@@ -507,6 +569,1114 @@ mod tests {
             )
         );
     }
+    #[tokio::test]
+    async fn test_migrate_already_applied() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Add member");
+        // Applying migrations again must not touch existing data
+        db_int
+            .migrate()
+            .await
+            .expect("Should be able to run migrations twice");
+        let result = db_int
+            .fetch_tracked_member(123, 456)
+            .await
+            .expect("Should get a result");
+        assert!(result.is_some());
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_learn_message_tracked_channel_tracked_member() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_channel(1056949566718607391, 1056949567561674808)
+            .await
+            .expect("Should be able to add tracked channel");
+        db_int
+            .add_tracked_member(1056949566718607391, 611962779762688020)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_learning_member(1056949566718607391, 1056949567561674808, 611962779762688020)
+            .await
+            .expect("Should be able to check message")
+            .expect("Should learn from a tracked member in a tracked channel");
+        let learned = db_int
+            .learn_member_message(member_id, Some(1056949567561674808), "Hello there, friend!")
+            .await
+            .expect("Should be able to learn from message");
+        assert!(learned);
+        let member_id = db_int
+            .fetch_member(1056949566718607391, 611962779762688020)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        let learned_rows = sqlx::query!(
+            "SELECT * FROM member_words WHERE member_id = $1",
+            member_id as i32
+        )
+        .fetch_all(&db_int.db)
+        .await
+        .expect("Should be able to get rows")
+        .len();
+        // hello, there, the comma, friend and the exclamation mark, between the begin and end of message tokens
+        assert_eq!(6, learned_rows);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_learn_message_untracked_channel() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_member(1056949566718607391, 611962779762688020)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_learning_member(1056949566718607391, 1056949567561674808, 611962779762688020)
+            .await
+            .expect("Should be able to check message");
+        assert!(member_id.is_none());
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_update_channel_backfill() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_channel(1056949566718607391, 1056949567561674808)
+            .await
+            .expect("Should be able to add tracked channel");
+        let not_started = db_int
+            .fetch_channel_backfill(1056949567561674808)
+            .await
+            .expect("Should be able to fetch backfill");
+        assert!(not_started.is_none());
+        db_int
+            .update_channel_backfill(1056949567561674808, Some(1067000000000000000), false)
+            .await
+            .expect("Should be able to record backfill progress");
+        db_int
+            .update_channel_backfill(1056949567561674808, Some(1060000000000000000), true)
+            .await
+            .expect("Should be able to record backfill progress");
+        let backfill = db_int
+            .fetch_channel_backfill(1056949567561674808)
+            .await
+            .expect("Should be able to fetch backfill")
+            .expect("Should have a backfill here");
+        assert_eq!(Some(1060000000000000000), backfill.oldest_message_id);
+        assert!(backfill.complete);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_start_channel_backfill_keeps_progress() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_channel(1056949566718607391, 1056949567561674810)
+            .await
+            .expect("Should be able to add tracked channel");
+        db_int
+            .start_channel_backfill(1056949567561674810, 1067000000000000000)
+            .await
+            .expect("Should be able to start backfill");
+        let started = db_int
+            .fetch_channel_backfill(1056949567561674810)
+            .await
+            .expect("Should be able to fetch backfill")
+            .expect("Should have a backfill here");
+        assert_eq!(Some(1067000000000000000), started.oldest_message_id);
+        assert!(!started.complete);
+        db_int
+            .update_channel_backfill(1056949567561674810, Some(1060000000000000000), false)
+            .await
+            .expect("Should be able to record backfill progress");
+        // Tracking the channel again must not throw away what was already learned
+        db_int
+            .start_channel_backfill(1056949567561674810, 1069000000000000000)
+            .await
+            .expect("Should be able to start backfill");
+        let resumed = db_int
+            .fetch_channel_backfill(1056949567561674810)
+            .await
+            .expect("Should be able to fetch backfill")
+            .expect("Should have a backfill here");
+        assert_eq!(Some(1060000000000000000), resumed.oldest_message_id);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_channel_webhook() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_channel(1056949566718607391, 1056949567561674809)
+            .await
+            .expect("Should be able to add tracked channel");
+        assert!(db_int
+            .fetch_channel_webhook(1056949567561674809)
+            .await
+            .expect("Should be able to fetch webhook")
+            .is_none());
+        let webhook = entities::ChannelWebhook {
+            webhook_id: 1070000000000000000,
+            token: "first-token".to_owned(),
+        };
+        db_int
+            .set_channel_webhook(1056949567561674809, &webhook)
+            .await
+            .expect("Should be able to record webhook");
+        let replacement = entities::ChannelWebhook {
+            webhook_id: 1070000000000000001,
+            token: "second-token".to_owned(),
+        };
+        db_int
+            .set_channel_webhook(1056949567561674809, &replacement)
+            .await
+            .expect("Should be able to replace webhook");
+        assert_eq!(
+            Some(replacement.clone()),
+            db_int
+                .fetch_channel_webhook(1056949567561674809)
+                .await
+                .expect("Should be able to fetch webhook")
+        );
+        assert_eq!(
+            Some(replacement),
+            db_int
+                .remove_channel_webhook(1056949567561674809)
+                .await
+                .expect("Should be able to remove webhook")
+        );
+        assert!(db_int
+            .remove_channel_webhook(1056949567561674809)
+            .await
+            .expect("Should be able to remove webhook")
+            .is_none());
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_game_leaderboard() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        let server_id = 1056949566718607392;
+        for (user_id, wins) in [(200, 1), (100, 3), (300, 1)] {
+            for _ in 0..wins {
+                db_int
+                    .add_game_win(server_id, user_id)
+                    .await
+                    .expect("Should be able to record a win");
+            }
+        }
+        assert_eq!(
+            4,
+            db_int
+                .add_game_win(server_id, 100)
+                .await
+                .expect("Should be able to record a win")
+        );
+        let leaderboard = db_int
+            .fetch_leaderboard(server_id, 2)
+            .await
+            .expect("Should be able to fetch leaderboard");
+        assert_eq!(
+            vec![
+                entities::GameScore {
+                    user_id: 100,
+                    wins: 4
+                },
+                entities::GameScore {
+                    user_id: 200,
+                    wins: 1
+                },
+            ],
+            leaderboard
+        );
+        assert_eq!(
+            errors::RemoveResult::Removed,
+            db_int
+                .reset_leaderboard(server_id)
+                .await
+                .expect("Should be able to reset leaderboard")
+        );
+        assert!(db_int
+            .fetch_leaderboard(server_id, 10)
+            .await
+            .expect("Should be able to fetch leaderboard")
+            .is_empty());
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_add_member_words_learns_longer_contexts() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        let test_words = vec![
+            "i".to_owned(),
+            "like".to_owned(),
+            "pie".to_owned(),
+            "a".to_owned(),
+            "lot".to_owned(),
+        ];
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        let word_ids = db_int
+            .add_words(test_words)
+            .await
+            .expect("Should be able to add words here");
+        db_int
+            .add_member_word_ids(member_id, &word_ids)
+            .await
+            .expect("Should be able to add member words here");
+        // Three contexts of two words and two contexts of three words
+        let found_rows = sqlx::query!(
+            "SELECT array_length(context, 1) AS order FROM member_ngrams WHERE member_id = $1",
+            member_id as i32
+        )
+        .fetch_all(&db_int.db)
+        .await
+        .expect("Should be able to get rows");
+        assert_eq!(
+            3,
+            found_rows
+                .iter()
+                .filter(|record| record.order == Some(2))
+                .count()
+        );
+        assert_eq!(
+            2,
+            found_rows
+                .iter()
+                .filter(|record| record.order == Some(3))
+                .count()
+        );
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_set_markov_order() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        let default_order = db_int
+            .fetch_markov_order(1056949566718607391)
+            .await
+            .expect("Should be able to fetch the order");
+        assert_eq!(
+            crate::client::textgen::textprocessing::DEFAULT_MARKOV_ORDER,
+            default_order
+        );
+        db_int
+            .set_markov_order(1056949566718607391, 3)
+            .await
+            .expect("Should be able to set the order");
+        let order = db_int
+            .fetch_markov_order(1056949566718607391)
+            .await
+            .expect("Should be able to fetch the order");
+        assert_eq!(3, order);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_words_keeps_order_and_repeats() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        let word_ids = db_int
+            .add_words(vec!["the".to_owned(), "cat".to_owned(), "sat".to_owned()])
+            .await
+            .expect("Should be able to add words");
+        // the cat sat the the cat, which is neither in word_id order nor free of repeats
+        let sequence = vec![
+            word_ids[0],
+            word_ids[1],
+            word_ids[2],
+            word_ids[0],
+            word_ids[0],
+            word_ids[1],
+        ];
+        let words = db_int
+            .fetch_words(&sequence)
+            .await
+            .expect("Should be able to fetch words");
+        assert_eq!(
+            vec!["the", "cat", "sat", "the", "the", "cat"],
+            words
+                .into_iter()
+                .map(|word| word.key)
+                .collect::<Vec<String>>()
+        );
+        let reversed: Vec<i32> = sequence.into_iter().rev().collect();
+        let words = db_int
+            .fetch_words(&reversed)
+            .await
+            .expect("Should be able to fetch words");
+        assert_eq!(
+            vec!["cat", "the", "the", "sat", "cat", "the"],
+            words
+                .into_iter()
+                .map(|word| word.key)
+                .collect::<Vec<String>>()
+        );
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_words_most_common_forms() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        for message in ["They love NASA", "They love NASA", "they love nasa"] {
+            db_int
+                .learn_member_message(member_id, None, message)
+                .await
+                .expect("Should be able to learn from message");
+        }
+        let word_ids = db_int
+            .fetch_word_ids(&["they".to_owned(), "nasa".to_owned()])
+            .await
+            .expect("Should be able to fetch word_ids")
+            .expect("Should know both words");
+        let words = db_int
+            .fetch_words(&word_ids)
+            .await
+            .expect("Should be able to fetch words");
+        assert_eq!(
+            vec![
+                SurfaceForms {
+                    key: "they".to_owned(),
+                    sentence_start: Some("They".to_owned()),
+                    elsewhere: None,
+                },
+                SurfaceForms {
+                    key: "nasa".to_owned(),
+                    sentence_start: None,
+                    elsewhere: Some("NASA".to_owned()),
+                },
+            ],
+            words
+        );
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_single_learned_message() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        // With three words of context there is only one possible walk, even though "the" repeats
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(vec![(member_id, 1.0)]),
+            3,
+            1.0,
+            None,
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("The cat sat on the mat", sentence);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_from_seed() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(vec![(member_id, 1.0)]),
+            3,
+            1.0,
+            Some("Cat sat"),
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("cat sat on the mat", sentence);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_from_unused_seed() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        db_int
+            .add_tracked_member(123, 789)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        let other_member_id = db_int
+            .fetch_member(123, 789)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        db_int
+            .learn_member_message(other_member_id, None, "A dog barked at the cat")
+            .await
+            .expect("Should be able to learn from message");
+        // Words the member never used, and words they used but never in that order, are both rejected
+        for seed in ["dog", "mat sat", "unknown"] {
+            match markov::generate_sentence(
+                &db_int,
+                &entities::Personality::Members(vec![(member_id, 1.0)]),
+                2,
+                1.0,
+                Some(seed),
+                entities::SeedPosition::Start,
+            )
+            .await
+            {
+                Err(errors::MarkovFetchResultError::SeedNotFound(unused_seed)) => {
+                    assert_eq!(seed, unused_seed)
+                }
+                other => panic!("Expected the seed to be rejected, got {:?}", other),
+            }
+        }
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_mashup() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        db_int
+            .add_tracked_member(123, 789)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        let other_member_id = db_int
+            .fetch_member(123, 789)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        db_int
+            .learn_member_message(other_member_id, None, "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn from message");
+        let members = [(member_id, 1.0), (other_member_id, 2.5)];
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(members.to_vec()),
+            3,
+            1.0,
+            None,
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert!(
+            ["The cat sat on the mat", "Some dog barked at birds"].contains(&sentence.as_str()),
+            "{}",
+            sentence
+        );
+        // Seeds only have to be used by one of the members
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(members.to_vec()),
+            3,
+            1.0,
+            Some("dog"),
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("dog barked at birds", sentence);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_channel_and_server() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        db_int
+            .add_tracked_channel(123, 111)
+            .await
+            .expect("Should be able to add tracked channel");
+        db_int
+            .add_tracked_channel(123, 222)
+            .await
+            .expect("Should be able to add tracked channel");
+        learn_message(&db_int, 123, 111, 456, "The cat sat on the mat").await;
+        learn_message(&db_int, 123, 222, 456, "Some dog barked at birds").await;
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Channel(111),
+            3,
+            1.0,
+            None,
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("The cat sat on the mat", sentence);
+        // The other channel's words only count for the whole server
+        match markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Channel(111),
+            3,
+            1.0,
+            Some("dog"),
+            entities::SeedPosition::Start,
+        )
+        .await
+        {
+            Err(errors::MarkovFetchResultError::SeedNotFound(_)) => {}
+            other => panic!("Expected the seed to be rejected, got {:?}", other),
+        }
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Server(123),
+            3,
+            1.0,
+            Some("dog"),
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("dog barked at birds", sentence);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_set_reply_persona() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        assert_eq!(
+            entities::ReplyPersona::Channel,
+            db_int
+                .fetch_reply_persona(123)
+                .await
+                .expect("Should be able to fetch the reply persona")
+        );
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .set_reply_persona(123, entities::ReplyPersona::Member(member_id))
+            .await
+            .expect("Should be able to set the reply persona");
+        assert_eq!(
+            entities::ReplyPersona::Member(member_id),
+            db_int
+                .fetch_reply_persona(123)
+                .await
+                .expect("Should be able to fetch the reply persona")
+        );
+        assert_eq!(
+            Some(456),
+            db_int
+                .fetch_member_user(member_id)
+                .await
+                .expect("Should be able to fetch the user")
+        );
+        db_int
+            .set_reply_persona(123, entities::ReplyPersona::Server)
+            .await
+            .expect("Should be able to set the reply persona");
+        assert_eq!(
+            entities::ReplyPersona::Server,
+            db_int
+                .fetch_reply_persona(123)
+                .await
+                .expect("Should be able to fetch the reply persona")
+        );
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_reply_from_seeds() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        db_int
+            .learn_member_message(member_id, None, "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn from message");
+        let personality = entities::Personality::Members(vec![(member_id, 1.0)]);
+        // Seeds that were never used are skipped
+        let seeds = vec!["unicorn".to_owned(), "dog".to_owned(), "cat".to_owned()];
+        let sentence = markov::generate_reply_sentence(&db_int, &personality, 3, 1.0, &seeds)
+            .await
+            .expect("Should be able to generate a sentence");
+        assert_eq!("Some dog barked at birds", sentence);
+        // Without any usable seed the reply starts from the beginning of a message
+        let seeds = vec!["unicorn".to_owned()];
+        let sentence = markov::generate_reply_sentence(&db_int, &personality, 3, 1.0, &seeds)
+            .await
+            .expect("Should be able to generate a sentence");
+        assert!(
+            ["The cat sat on the mat", "Some dog barked at birds"].contains(&sentence.as_str()),
+            "{}",
+            sentence
+        );
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_around_seed() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        db_int
+            .add_tracked_channel(123, 111)
+            .await
+            .expect("Should be able to add tracked channel");
+        learn_message(&db_int, 123, 111, 456, "The cat sat on the mat").await;
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        // Every word of the sentence is reached from the middle. A single word of context can't tell which "the" it is at
+        for markov_order in 2..=3 {
+            for seed in ["sat", "sat on"] {
+                let sentence = markov::generate_sentence(
+                    &db_int,
+                    &entities::Personality::Members(vec![(member_id, 1.0)]),
+                    markov_order,
+                    1.0,
+                    Some(seed),
+                    entities::SeedPosition::Anywhere,
+                )
+                .await
+                .expect("Should be able to generate a sentence");
+                assert_eq!("The cat sat on the mat", sentence);
+            }
+        }
+        for personality in [
+            entities::Personality::Channel(111),
+            entities::Personality::Server(123),
+        ] {
+            let sentence = markov::generate_sentence(
+                &db_int,
+                &personality,
+                2,
+                1.0,
+                Some("on"),
+                entities::SeedPosition::Anywhere,
+            )
+            .await
+            .expect("Should be able to generate a sentence");
+            assert_eq!("The cat sat on the mat", sentence);
+        }
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_rejects_copies() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        assert_eq!(
+            0.5,
+            db_int
+                .fetch_originality_threshold(123)
+                .await
+                .expect("Should be able to fetch the originality threshold")
+        );
+        // The only sentence that can be generated is the learned message itself
+        match markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(vec![(member_id, 1.0)]),
+            2,
+            1.0,
+            None,
+            entities::SeedPosition::Start,
+        )
+        .await
+        {
+            Err(errors::MarkovFetchResultError::NotOriginal) => {}
+            other => panic!("Expected the copy to be rejected, got {:?}", other),
+        }
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(vec![(member_id, 1.0)]),
+            2,
+            1.0,
+            None,
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("The cat sat on the mat", sentence);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_generate_from_cached_model_after_learning() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+        // Only the chain is tested here, so copies of learned messages are let through
+        db_int
+            .set_originality_threshold(123, 1.0)
+            .await
+            .expect("Should be able to set the originality threshold");
+
+        db_int
+            .add_tracked_member(123, 456)
+            .await
+            .expect("Should be able to add tracked member");
+        let member_id = db_int
+            .fetch_member(123, 456)
+            .await
+            .expect("Should get a result")
+            .expect("Should have a member here");
+        db_int
+            .learn_member_message(member_id, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn from message");
+        // Generating loads the member's model into the cache
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(vec![(member_id, 1.0)]),
+            3,
+            1.0,
+            None,
+            entities::SeedPosition::Start,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("The cat sat on the mat", sentence);
+        // Messages learned afterwards are added to the cached model
+        db_int
+            .learn_member_message(member_id, None, "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn from message");
+        let sentence = markov::generate_sentence(
+            &db_int,
+            &entities::Personality::Members(vec![(member_id, 1.0)]),
+            3,
+            1.0,
+            Some("dog"),
+            entities::SeedPosition::Anywhere,
+        )
+        .await
+        .expect("Should be able to generate a sentence");
+        assert_eq!("Some dog barked at birds", sentence);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
+    #[tokio::test]
+    async fn test_set_chime_settings() {
+        dotenv::dotenv().expect("Should be able to get .env file");
+        let db_url: String =
+            std::env::var("DATABASE_URL").expect("Should be able to get database url");
+        let db_int = DbInterface::new(&db_url).await;
+
+        assert_eq!(
+            None,
+            db_int
+                .fetch_chime_settings(789)
+                .await
+                .expect("Should be able to fetch chime settings")
+        );
+        let settings = entities::ChimeSettings {
+            every_n_messages: Some(10),
+            probability: 0.25,
+            cooldown_seconds: 120,
+        };
+        db_int
+            .set_chime_settings(123, 789, settings)
+            .await
+            .expect("Should be able to set chime settings");
+        assert_eq!(
+            Some(settings),
+            db_int
+                .fetch_chime_settings(789)
+                .await
+                .expect("Should be able to fetch chime settings")
+        );
+        let result = db_int
+            .remove_chime_settings(789)
+            .await
+            .expect("Should be able to remove chime settings");
+        assert_eq!(errors::RemoveResult::Removed, result);
+        let result = db_int
+            .remove_chime_settings(789)
+            .await
+            .expect("Should be able to remove chime settings");
+        assert_eq!(errors::RemoveResult::NotPresent, result);
+        db_int
+            .reinit_all_data()
+            .await
+            .expect("Clear data after test");
+    }
+
     // #[tokio::test]
     // async fn test_schema_output() {
     //     use std::env;
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use crate::client::textgen::textprocessing;
+
+/** How long players have to guess when no time limit is given. */
+pub const DEFAULT_GUESS_SECONDS: u64 = 30;
+/** The shortest time limit that can be given, so players have a chance to read the sentence. */
+pub const MIN_GUESS_SECONDS: u64 = 10;
+/** The longest time limit that can be given, so a forgotten round doesn't hold up a channel. */
+pub const MAX_GUESS_SECONDS: u64 = 300;
+/** How many tracked members are tried before giving up on generating a sentence, since some may not have said enough yet. */
+pub const MAX_GENERATION_ATTEMPTS: usize = 5;
+/** How many players the leaderboard shows. */
+pub const LEADERBOARD_SIZE: u32 = 10;
+
+/** A member who could have said the sentence, with the names players may call them by. */
+#[derive(Debug, Clone)]
+pub struct Suspect {
+    pub user_id: u64,
+    names: Vec<String>,
+}
+
+impl Suspect {
+    /** Names are matched case insensitively, so players don't have to get the exact spelling of a nickname right. */
+    pub fn new(user_id: u64, names: &[String]) -> Self {
+        let mut normalized: Vec<String> = names.iter().map(|name| normalize_name(name)).collect();
+        normalized.retain(|name| !name.is_empty());
+        normalized.dedup();
+        Self {
+            user_id,
+            names: normalized,
+        }
+    }
+}
+
+/** How a message sent during a round was judged. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guess {
+    /** The player named the member the sentence imitates, and wins the round. */
+    Correct,
+    /** The player named another suspect, given by user_id. */
+    Wrong(u64),
+    /** The player already used their guess. */
+    AlreadyGuessed,
+    /** The message names no suspect, so it's just chatter. */
+    Chatter,
+}
+
+/** One round of "Who said it?". Kept in memory only, since a round lasts no longer than its time limit. */
+#[derive(Debug)]
+pub struct Round {
+    answer: u64,
+    suspects: Vec<Suspect>,
+    guessed: HashSet<u64>,
+}
+
+impl Round {
+    /** Starts a round where the sentence imitates the suspect with the user_id `answer`. */
+    pub fn new(answer: u64, suspects: Vec<Suspect>) -> Self {
+        Self {
+            answer,
+            suspects,
+            guessed: HashSet::new(),
+        }
+    }
+
+    /** The user_id of the member the sentence imitates. */
+    pub fn answer(&self) -> u64 {
+        self.answer
+    }
+
+    /** Judges a message sent during the round. Players guess by mentioning a suspect or writing nothing but their name, and only get one guess. */
+    pub fn guess(&mut self, player: u64, content: &str, mentioned: &[u64]) -> Guess {
+        let suspect = match self.named_suspect(content, mentioned) {
+            Some(suspect) => suspect,
+            None => return Guess::Chatter,
+        };
+        if !self.guessed.insert(player) {
+            return Guess::AlreadyGuessed;
+        }
+        if suspect == self.answer {
+            Guess::Correct
+        } else {
+            Guess::Wrong(suspect)
+        }
+    }
+
+    /** Returns the user_id of the suspect a message names, if any. Mentions win over names. */
+    fn named_suspect(&self, content: &str, mentioned: &[u64]) -> Option<u64> {
+        if let Some(user_id) = mentioned.iter().find(|user_id| {
+            self.suspects
+                .iter()
+                .any(|suspect| suspect.user_id == **user_id)
+        }) {
+            return Some(*user_id);
+        }
+        let name = normalize_name(content.trim().trim_start_matches('@'));
+        self.suspects
+            .iter()
+            .find(|suspect| suspect.names.contains(&name))
+            .map(|suspect| suspect.user_id)
+    }
+}
+
+/** Case folds a name the same way words are, keeping the spaces between its words. */
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(textprocessing::normalize)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round() -> Round {
+        Round::new(
+            1,
+            vec![
+                Suspect::new(1, &["Alice".to_owned(), "alice_w".to_owned()]),
+                Suspect::new(2, &["Bob  the Builder".to_owned()]),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_guess_by_name() {
+        let mut round = round();
+        assert_eq!(Guess::Wrong(2), round.guess(10, "bob the BUILDER", &[]));
+        assert_eq!(Guess::Correct, round.guess(11, " @ALICE_W ", &[]));
+    }
+
+    #[test]
+    fn test_guess_by_mention() {
+        let mut round = round();
+        assert_eq!(Guess::Correct, round.guess(10, "<@1> for sure", &[3, 1]));
+        assert_eq!(Guess::Wrong(2), round.guess(11, "<@2>", &[2]));
+    }
+
+    #[test]
+    fn test_only_one_guess_each() {
+        let mut round = round();
+        assert_eq!(Guess::Wrong(2), round.guess(10, "bob the builder", &[]));
+        assert_eq!(Guess::AlreadyGuessed, round.guess(10, "alice", &[]));
+    }
+
+    #[test]
+    fn test_chatter_is_not_a_guess() {
+        let mut round = round();
+        assert_eq!(Guess::Chatter, round.guess(10, "this one is hard", &[]));
+        assert_eq!(Guess::Chatter, round.guess(10, "i think alice", &[3]));
+        assert_eq!(Guess::Correct, round.guess(10, "Alice", &[]));
+    }
+}
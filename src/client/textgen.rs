@@ -1,2 +1,8 @@
 pub mod markov;
+// Only tests use the in-memory model so far
+#[cfg(test)]
+pub mod memory;
+pub mod model;
+pub mod originality;
 pub mod textprocessing;
+pub mod transitions;
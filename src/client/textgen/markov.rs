@@ -1,73 +1,75 @@
+use itertools::Itertools;
 use lazy_static::lazy_static;
-use regex::Captures;
+use rand::Rng;
 use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
 
-/** Extracts complete sentences from a string into a vector. Expects all newlines and tab characters to be replaced with spaces*/
-fn extract_sentences(string_buffer: &str) -> Vec<&str> {
-    // expects \n and \t to be replaced with " " in the string_buffer
-    lazy_static! {
-        static ref SENTENCE_EXTRACT: Regex =
-            Regex::new("[A-Za-z0-9'\";:,]([A-Za-z0-9\\s'\";:,]|\\.[^\\s])*[.!?]")
-                .expect("Should be valid regex");
-    } // Make this lazy static since this is an expensive operation and we only need it compiled once.
-    SENTENCE_EXTRACT
-        .find_iter(string_buffer)
-        .map(|thing| thing.as_str())
-        .collect()
-}
+use crate::client::database::entities::{Personality, SeedPosition};
+use crate::client::database::errors::MarkovFetchResultError;
+use crate::client::textgen::model::TransitionSource;
+use crate::client::textgen::{originality, textprocessing};
+
+/** The temperature that draws words exactly as often as they were seen. */
+pub const DEFAULT_TEMPERATURE: f64 = 1.0;
+/** The lowest temperature accepted from commands. Lower temperatures almost always draw the most common word. */
+pub const MIN_TEMPERATURE: f64 = 0.1;
+/** The highest temperature accepted from commands. Higher temperatures draw every word almost equally often. */
+pub const MAX_TEMPERATURE: f64 = 5.0;
 
 /// Filters a string so it can be inserted into the Markov data set.
 ///
-/// Removes links, User IDs, emotes, animated emotes, non alphanumeric characters, line feeds, extra whitespace, and role IDs.
+/// Removes links, symbols, line feeds and extra whitespace. User, role and channel mentions are replaced with placeholders.
 ///
-/// Replaces uppercase letters with their lowercase variants.
+/// Casing, emoji and the punctuation that `textprocessing::tokenize` keeps as tokens are left alone, so generated text can be typed the way it was learned.
 pub fn filter_string_pre_processing(msg: &str) -> String {
-    lazy_static! {static ref LINK_EXTRACT: Regex = Regex::new(r#"(?:(?:https?|ftp)://|\b(?:[a-z\d]+\.))(?:(?:[^\s()<>]+|\((?:[^\s()<>]+|(?:\([^\s()<>]+\)))?\))+(?:\((?:[^\s()<>]+|(?:\(?:[^\s()<>]+\)))?\)|[^\s`!()\[\]{};:'".,<>?«»“”‘’]))?"#)
-    .expect("Invalid regular expression");}
+    // Accents typed as separate combining characters are composed, so the same word is always typed the same way
+    let msg: String = msg.nfc().collect();
+    lazy_static! {
+        static ref LINK_EXTRACT: Regex =
+            Regex::new(r"(?:(?:https?|ftp)://|\bwww\.)\S+").expect("Invalid regular expression");
+    }
+    let str = textprocessing::mention_placeholders(&LINK_EXTRACT.replace_all(&msg, ""));
 
-    let mut str = LINK_EXTRACT.replace_all(msg, "").into_owned();
-    while str.ends_with(' ') {
-        str.pop();
+    // Custom emoji and mention placeholders are made of symbols, so they are kept whole and only the text around them is filtered
+    let mut filtered_message = String::new();
+    let mut text_start = 0;
+    for whole_token in textprocessing::WHOLE_TOKEN.find_iter(&str) {
+        filtered_message.push_str(&filter_text(&str[text_start..whole_token.start()]));
+        filtered_message.push_str(whole_token.as_str());
+        text_start = whole_token.end();
     }
+    filtered_message.push_str(&filter_text(&str[text_start..]));
+    filtered_message.split_whitespace().join(" ")
+}
 
-    let mut filtered_message = str;
+/** Removes what can't be learned from text without custom emoji or mention placeholders in it, as described for `filter_string_pre_processing`. */
+fn filter_text(text: &str) -> String {
+    let mut filtered_message = text.to_owned();
     static PATTERNS: once_cell::sync::OnceCell<Vec<Regex>> = once_cell::sync::OnceCell::new();
-    PATTERNS
-        .set(vec![
-            Regex::new(r"<:?(\w+:)(\d+)>").expect("Invalid regular expression"), //emote regex
-            Regex::new(r"<a:?(\w+:)(\d+)>").expect("Invalid regular expression"), //animated emote regex
-            Regex::new(r#"[,.!"\#$()=?*<>{}\[\]\\\|Łł@*;:+~ˇ^˘°˛`´˝]"#)
-                .expect("Invalid regular expression"), //non alphanumeric regex
-            Regex::new(r"^(\d{18})$").expect("Invalid regular expression"), //remaining numbers from users regex
-            Regex::new(r"\n").expect("Invalid regular expression"),         //line feed regex
+    let patterns = PATTERNS.get_or_init(|| {
+        vec![
+            Regex::new(r#"["\#$()=*<>{}\[\]\\\|@*+~ˇ^˘°˛`´˝]"#)
+                .expect("Invalid regular expression"), //symbol regex
+            Regex::new(r"\n").expect("Invalid regular expression"), //line feed regex
             Regex::new(r"[ ]{3}|[ ]{2}").expect("Invalid regular expression"), //double and triple whitespace regex
-            Regex::new(r"<@&(\d+)>").expect("Invalid regular expression"),     // role regex
-        ])
-        .unwrap();
-
-    let upper_case_regex = Regex::new(r"[A-Z][a-z0-9_-]{1,}").expect("Invalid regular expression");
+        ]
+    });
 
     loop {
         let mut number_of_matches: u16 = 0;
 
-        for regex in PATTERNS.get().unwrap() {
+        for regex in patterns {
             while regex.is_match(&filtered_message) {
                 number_of_matches += 1;
                 filtered_message = regex.replace_all(&filtered_message, " ").into_owned();
             }
         }
-        while upper_case_regex.is_match(&filtered_message) {
-            number_of_matches += 1;
-            filtered_message = upper_case_regex
-                .replace(&filtered_message, |caps: &Captures| caps[0].to_lowercase())
-                .into_owned();
-        }
         if number_of_matches == 0 {
             break;
         }
     }
 
-    return filtered_message.trim().to_owned();
+    filtered_message
 }
 // /**Formats all files in the corpi/ directory into the proper form for a markov chain reader. In the bot, this would be called with a value of true*/
 // fn format_corpus_files(delete_old_files: bool) {
@@ -106,3 +108,360 @@ pub fn filter_string_pre_processing(msg: &str) -> String {
 //             .expect("Should be able to write to created file.");
 //     }
 // }
+
+/// Draws a word from `(word_id, frequency)` successors with probability proportional to `frequency^(1 / temperature)`.
+///
+/// Frequencies don't have to be whole numbers, so counts that were scaled by a weight can be drawn from too.
+///
+/// A temperature of 1 draws words as often as they were seen, lower temperatures favour common words and higher temperatures favour rare ones.
+/// Returns `None` if there are no successors.
+pub fn weighted_choice<R: Rng>(
+    successors: &[(i32, f64)],
+    temperature: f64,
+    rng: &mut R,
+) -> Option<i32> {
+    let max_frequency = successors
+        .iter()
+        .map(|(_, frequency)| *frequency)
+        .reduce(f64::max)?;
+    if max_frequency <= 0.0 {
+        return None;
+    }
+    // Scale by the largest frequency first so low temperatures can't overflow
+    let weights: Vec<f64> = successors
+        .iter()
+        .map(|(_, frequency)| (frequency / max_frequency).powf(1.0 / temperature))
+        .collect();
+    let mut remaining = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for ((word_id, _), weight) in successors.iter().zip(weights.iter()) {
+        if remaining < *weight {
+            return Some(*word_id);
+        }
+        remaining -= weight;
+    }
+    // Only reachable through floating point rounding
+    successors.last().map(|(word_id, _)| *word_id)
+}
+
+/// Generates a sentence that sounds like a personality by walking its transitions, sampled at the given temperature, from the begin of message token until the end of message token is drawn.
+///
+/// A seed starts the sentence, or is first walked backwards from until the begin of message token is drawn if it may be anywhere. Sentences that end too early or copy learned messages are generated again, up to a limit. The sentence length bounds are only used as safety limits.
+pub async fn generate_sentence<S: TransitionSource + ?Sized>(
+    source: &S,
+    personality: &Personality,
+    markov_order: u8,
+    temperature: f64,
+    seed: Option<&str>,
+    seed_position: SeedPosition,
+) -> Result<String, MarkovFetchResultError> {
+    // Nothing has been learned since the sentinel tokens were introduced if they aren't known words
+    let (begin_word_id, end_word_id) = match source.sentinel_word_ids().await? {
+        Some(sentinel_word_ids) => sentinel_word_ids,
+        None => {
+            return Err(MarkovFetchResultError::NotEnoughWords(
+                textprocessing::MIN_SENTENCE_LENGTH,
+                0,
+            ))
+        }
+    };
+    // Sentences start from the seed if there is one, otherwise from the begin of message token
+    let (start_word_ids, skipped) = match seed {
+        Some(seed) => (seed_word_ids(source, personality, seed).await?, 0),
+        None => (vec![begin_word_id], 1),
+    };
+    let walk_backwards = seed.is_some() && seed_position == SeedPosition::Anywhere;
+    let originality_threshold = source.originality_threshold(personality).await?;
+    let mut longest_length = 0;
+    let mut copied = false;
+    for _ in 0..textprocessing::GENERATION_ATTEMPTS {
+        let mut attempt_word_ids = start_word_ids.clone();
+        let mut skipped = skipped;
+        // Grow the sentence backwards from the seed until the begin of message token is drawn, using as many of the first words as context as the order allows
+        while walk_backwards
+            && attempt_word_ids.len() < textprocessing::MAX_SENTENCE_LENGTH as usize
+        {
+            let context_end = attempt_word_ids.len().min(markov_order as usize);
+            match previous_word(
+                source,
+                personality,
+                &attempt_word_ids[..context_end],
+                temperature,
+            )
+            .await?
+            {
+                Some(previous_word_id) => {
+                    attempt_word_ids.insert(0, previous_word_id);
+                    // Keep the begin of message token as context for the forward walk
+                    if previous_word_id == begin_word_id {
+                        skipped = 1;
+                        break;
+                    }
+                }
+                // Nothing ever came before the context
+                None => break,
+            }
+        }
+        // The begin of message token doesn't count towards the length
+        while attempt_word_ids.len() - skipped < textprocessing::MAX_SENTENCE_LENGTH as usize {
+            // Use as many of the latest words as context as the order allows
+            let context_start = attempt_word_ids.len().saturating_sub(markov_order as usize);
+            match next_word(
+                source,
+                personality,
+                &attempt_word_ids[context_start..],
+                temperature,
+            )
+            .await?
+            {
+                Some(next_word_id) if next_word_id != end_word_id => {
+                    attempt_word_ids.push(next_word_id)
+                }
+                // Either the end of message token was drawn or nothing ever followed the context
+                _ => break,
+            }
+        }
+        attempt_word_ids.drain(..skipped);
+        longest_length = longest_length.max(attempt_word_ids.len());
+        if attempt_word_ids.len() < textprocessing::MIN_SENTENCE_LENGTH as usize {
+            continue;
+        }
+        if let Some(threshold) = originality_threshold {
+            // The sentence is checked as if it were a whole message, between the begin and end of message tokens
+            let mut message_word_ids = vec![begin_word_id];
+            message_word_ids.extend_from_slice(&attempt_word_ids);
+            message_word_ids.push(end_word_id);
+            let shingles = originality::shingle_sequence(&message_word_ids);
+            let known_shingles = source
+                .known_shingles(personality, &originality::shingle_hashes(&message_word_ids))
+                .await?;
+            if !originality::is_original(&shingles, &known_shingles, threshold) {
+                copied = true;
+                continue;
+            }
+        }
+        // Now get actual words from word_ids, typed the way they usually are:
+        return Ok(textprocessing::render(
+            &source.words(&attempt_word_ids).await?,
+        ));
+    }
+    if copied {
+        return Err(MarkovFetchResultError::NotOriginal);
+    }
+    Err(MarkovFetchResultError::NotEnoughWords(
+        textprocessing::MIN_SENTENCE_LENGTH,
+        u32::try_from(longest_length).expect("Should be able to convert vector length to u32 size"),
+    ))
+}
+
+/** Generates a sentence around the first of the seeds the personality has used. Falls back to an unseeded sentence if it never used any of them. */
+pub async fn generate_reply_sentence<S: TransitionSource + ?Sized>(
+    source: &S,
+    personality: &Personality,
+    markov_order: u8,
+    temperature: f64,
+    seeds: &[String],
+) -> Result<String, MarkovFetchResultError> {
+    for seed in seeds {
+        match generate_sentence(
+            source,
+            personality,
+            markov_order,
+            temperature,
+            Some(seed),
+            SeedPosition::Anywhere,
+        )
+        .await
+        {
+            Err(MarkovFetchResultError::SeedNotFound(_))
+            | Err(MarkovFetchResultError::NotEnoughWords(_, _))
+            | Err(MarkovFetchResultError::NotOriginal) => continue,
+            result => return result,
+        }
+    }
+    generate_sentence(
+        source,
+        personality,
+        markov_order,
+        temperature,
+        None,
+        SeedPosition::Start,
+    )
+    .await
+}
+
+/** Returns the word_ids of a seed that a personality has used, so generation can start from it. The last words of the seed must have been followed by something, up to as many words as the longest context learned. */
+async fn seed_word_ids<S: TransitionSource + ?Sized>(
+    source: &S,
+    personality: &Personality,
+    seed: &str,
+) -> Result<Vec<i32>, MarkovFetchResultError> {
+    let words: Vec<String> = textprocessing::tokenize(&filter_string_pre_processing(seed))
+        .iter()
+        .map(|token| textprocessing::normalize(token))
+        .collect();
+    let not_found = || MarkovFetchResultError::SeedNotFound(seed.to_owned());
+    if words.is_empty() {
+        return Err(not_found());
+    }
+    let word_ids = match source.word_ids(&words).await? {
+        Some(word_ids) => word_ids,
+        None => return Err(not_found()),
+    };
+    let context_start = word_ids
+        .len()
+        .saturating_sub(textprocessing::MAX_MARKOV_ORDER as usize);
+    if source
+        .successors(personality, &word_ids[context_start..])
+        .await?
+        .is_empty()
+    {
+        return Err(not_found());
+    }
+    Ok(word_ids)
+}
+
+/** Draws the word that follows a context for a personality, weighted by how often the context was followed by each word. Backs off to shorter contexts when the full context was never followed by anything. */
+async fn next_word<S: TransitionSource + ?Sized>(
+    source: &S,
+    personality: &Personality,
+    context: &[i32],
+    temperature: f64,
+) -> Result<Option<i32>, MarkovFetchResultError> {
+    for context_start in 0..context.len() {
+        let successors = source
+            .successors(personality, &context[context_start..])
+            .await?;
+        if let Some(next_word_id) =
+            weighted_choice(&successors, temperature, &mut rand::thread_rng())
+        {
+            return Ok(Some(next_word_id));
+        }
+    }
+    Ok(None)
+}
+
+/** Draws the word that came before a context for a personality, weighted by how often each word came before it. Backs off to shorter contexts, keeping the first words, when nothing ever came before the full context. */
+async fn previous_word<S: TransitionSource + ?Sized>(
+    source: &S,
+    personality: &Personality,
+    context: &[i32],
+    temperature: f64,
+) -> Result<Option<i32>, MarkovFetchResultError> {
+    for context_end in (1..=context.len()).rev() {
+        let predecessors = source
+            .predecessors(personality, &context[..context_end])
+            .await?;
+        if let Some(previous_word_id) =
+            weighted_choice(&predecessors, temperature, &mut rand::thread_rng())
+        {
+            return Ok(Some(previous_word_id));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_pre_processing_keeps_casing_and_punctuation() {
+        assert_eq!(
+            "Hello there, General Kenobi!",
+            filter_string_pre_processing("Hello there, @General Kenobi!")
+        );
+    }
+
+    #[test]
+    fn test_pre_processing_keeps_emoji() {
+        assert_eq!(
+            "nice <:pog:123> 😂",
+            filter_string_pre_processing("nice <:pog:123>   😂")
+        );
+    }
+
+    #[test]
+    fn test_pre_processing_keeps_mentions_as_placeholders() {
+        assert_eq!(
+            "hey <user:123>, ask <role:456> in <channel:789>",
+            filter_string_pre_processing("hey <@!123>, ask <@&456> in <#789>")
+        );
+    }
+
+    #[test]
+    fn test_pre_processing_removes_links() {
+        assert_eq!(
+            "Look at this. So cute",
+            filter_string_pre_processing("Look at this. https://example.com/cat.png So cute")
+        );
+    }
+
+    #[test]
+    fn test_weighted_choice_no_successors() {
+        assert_eq!(
+            None,
+            weighted_choice(&[], DEFAULT_TEMPERATURE, &mut rand::thread_rng())
+        );
+    }
+
+    #[test]
+    fn test_weighted_choice_lowest_draw() {
+        // StepRng at 0 always draws 0.0, which lands on the first successor
+        let mut rng = StepRng::new(0, 0);
+        assert_eq!(
+            Some(1),
+            weighted_choice(&[(1, 1.0), (2, 100.0)], DEFAULT_TEMPERATURE, &mut rng)
+        );
+    }
+
+    #[test]
+    fn test_weighted_choice_proportional() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let successors = [(1, 1.0), (2, 3.0)];
+        let draws = 10000;
+        let second_draws = (0..draws)
+            .filter(|_| weighted_choice(&successors, DEFAULT_TEMPERATURE, &mut rng) == Some(2))
+            .count();
+        // Expect about 75% of draws
+        assert!((7200..7800).contains(&second_draws));
+    }
+
+    #[test]
+    fn test_weighted_choice_fractional_frequencies() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let successors = [(1, 0.5), (2, 1.5)];
+        let draws = 10000;
+        let second_draws = (0..draws)
+            .filter(|_| weighted_choice(&successors, DEFAULT_TEMPERATURE, &mut rng) == Some(2))
+            .count();
+        // Expect about 75% of draws
+        assert!((7200..7800).contains(&second_draws));
+    }
+
+    #[test]
+    fn test_weighted_choice_low_temperature() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let successors = [(1, 1.0), (2, 3.0)];
+        let draws = 10000;
+        let second_draws = (0..draws)
+            .filter(|_| weighted_choice(&successors, MIN_TEMPERATURE, &mut rng) == Some(2))
+            .count();
+        // 3^10 to 1, so practically every draw
+        assert!(second_draws > 9900);
+    }
+
+    #[test]
+    fn test_weighted_choice_high_temperature() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let successors = [(1, 1.0), (2, 3.0)];
+        let draws = 10000;
+        let second_draws = (0..draws)
+            .filter(|_| weighted_choice(&successors, MAX_TEMPERATURE, &mut rng) == Some(2))
+            .count();
+        // 3^0.2 to 1 is about 56%
+        assert!((5200..6000).contains(&second_draws));
+    }
+}
@@ -0,0 +1,627 @@
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::client::database::entities::{Personality, SeedPosition};
+use crate::client::database::errors::{AddMemberWordsError, MarkovFetchResultError};
+use crate::client::textgen::model::{ModelStats, TextModel, TransitionSource};
+use crate::client::textgen::textprocessing::SurfaceForms;
+use crate::client::textgen::transitions::TransitionModel;
+use crate::client::textgen::{markov, originality, textprocessing};
+
+/// A text model kept entirely in memory, so the Markov chain logic can run without a database.
+///
+/// Everything it learns is forgotten when it is dropped. Learned messages are checked for copies across every server at once.
+#[derive(Debug)]
+pub struct MemoryModel {
+    state: Mutex<MemoryState>,
+    markov_order: u8,
+    originality_threshold: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    /** The word_id of every known word. */
+    word_ids: HashMap<String, i32>,
+    /** Every known word, by word_id. */
+    words: Vec<String>,
+    /** How often each word was typed each way, by word_id and whether it started a sentence. */
+    forms: HashMap<(i32, bool), HashMap<String, u64>>,
+    members: HashMap<u32, TransitionModel>,
+    channels: HashMap<u64, TransitionModel>,
+    /** The server of every channel, so a server sounds like all of its channels together. */
+    channel_servers: HashMap<u64, u64>,
+    shingle_hashes: HashSet<i64>,
+}
+
+impl MemoryState {
+    /** Returns the word_id of a word, adding it to the known words first if it is new. */
+    fn add_word(&mut self, word: &str) -> i32 {
+        if let Some(word_id) = self.word_ids.get(word) {
+            return *word_id;
+        }
+        let word_id = self.words.len() as i32;
+        self.words.push(word.to_owned());
+        self.word_ids.insert(word.to_owned(), word_id);
+        word_id
+    }
+
+    /** Returns the most common way a word was typed in a position, preferring the first in alphabetical order on a tie. */
+    fn most_common_form(&self, word_id: i32, sentence_start: bool) -> Option<String> {
+        self.forms
+            .get(&(word_id, sentence_start))?
+            .iter()
+            .max_by(|(form, frequency), (other_form, other_frequency)| {
+                frequency
+                    .cmp(other_frequency)
+                    .then_with(|| other_form.cmp(form))
+            })
+            .map(|(form, _)| form.clone())
+    }
+
+    /** Returns the transition models a personality is made of, along with the weight of each. */
+    fn models(&self, personality: &Personality) -> Vec<(&TransitionModel, f64)> {
+        match personality {
+            Personality::Members(members) => members
+                .iter()
+                .filter_map(|(member_id, weight)| {
+                    self.members.get(member_id).map(|model| (model, *weight))
+                })
+                .collect(),
+            Personality::Channel(channel_id) => self
+                .channels
+                .get(channel_id)
+                .map(|model| vec![(model, 1.0)])
+                .unwrap_or_default(),
+            Personality::Server(server_id) => self
+                .channels
+                .iter()
+                .filter(|(channel_id, _)| self.channel_servers.get(channel_id) == Some(server_id))
+                .map(|(_, model)| (model, 1.0))
+                .collect(),
+        }
+    }
+
+    /** Adds up the frequencies that a lookup returns for every model of a personality, scaled by their weight. */
+    fn combine(
+        &self,
+        personality: &Personality,
+        lookup: impl Fn(&TransitionModel) -> Vec<(i32, u64)>,
+    ) -> Vec<(i32, f64)> {
+        let mut frequencies: HashMap<i32, f64> = HashMap::new();
+        for (model, weight) in self.models(personality) {
+            for (word_id, frequency) in lookup(model) {
+                *frequencies.entry(word_id).or_insert(0.0) += frequency as f64 * weight;
+            }
+        }
+        frequencies.into_iter().collect()
+    }
+}
+
+impl MemoryModel {
+    /** Creates an empty model that generates with the given number of words of context. Without a threshold, copies of learned messages are let through. */
+    pub fn new(markov_order: u8, originality_threshold: Option<f64>) -> MemoryModel {
+        MemoryModel {
+            state: Mutex::new(MemoryState::default()),
+            markov_order,
+            originality_threshold,
+        }
+    }
+
+    /** Records which server a channel is in, so what is learned in it counts towards the server. */
+    pub fn add_channel(&self, server_id: u64, channel_id: u64) {
+        self.lock_state()
+            .channel_servers
+            .insert(channel_id, server_id);
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state
+            .lock()
+            .expect("Should be able to lock the in-memory model")
+    }
+}
+
+impl Default for MemoryModel {
+    fn default() -> Self {
+        MemoryModel::new(
+            textprocessing::DEFAULT_MARKOV_ORDER,
+            Some(originality::DEFAULT_ORIGINALITY_THRESHOLD),
+        )
+    }
+}
+
+#[async_trait]
+impl TransitionSource for MemoryModel {
+    async fn sentinel_word_ids(&self) -> Result<Option<(i32, i32)>, MarkovFetchResultError> {
+        let state = self.lock_state();
+        Ok(state
+            .word_ids
+            .get(textprocessing::BEGIN_OF_MESSAGE)
+            .copied()
+            .zip(state.word_ids.get(textprocessing::END_OF_MESSAGE).copied()))
+    }
+
+    async fn word_ids(&self, words: &[String]) -> Result<Option<Vec<i32>>, MarkovFetchResultError> {
+        let state = self.lock_state();
+        Ok(words
+            .iter()
+            .map(|word| state.word_ids.get(word).copied())
+            .collect())
+    }
+
+    async fn words(&self, word_ids: &[i32]) -> Result<Vec<SurfaceForms>, MarkovFetchResultError> {
+        let state = self.lock_state();
+        Ok(word_ids
+            .iter()
+            .filter_map(|word_id| {
+                state.words.get(*word_id as usize).map(|key| SurfaceForms {
+                    key: key.clone(),
+                    sentence_start: state.most_common_form(*word_id, true),
+                    elsewhere: state.most_common_form(*word_id, false),
+                })
+            })
+            .collect())
+    }
+
+    async fn successors(
+        &self,
+        personality: &Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, MarkovFetchResultError> {
+        Ok(self
+            .lock_state()
+            .combine(personality, |model| model.successors(context)))
+    }
+
+    async fn predecessors(
+        &self,
+        personality: &Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, MarkovFetchResultError> {
+        Ok(self
+            .lock_state()
+            .combine(personality, |model| model.predecessors(context)))
+    }
+
+    async fn originality_threshold(
+        &self,
+        _personality: &Personality,
+    ) -> Result<Option<f64>, MarkovFetchResultError> {
+        Ok(self.originality_threshold)
+    }
+
+    async fn known_shingles(
+        &self,
+        _personality: &Personality,
+        shingle_hashes: &[i64],
+    ) -> Result<HashSet<i64>, MarkovFetchResultError> {
+        let state = self.lock_state();
+        Ok(shingle_hashes
+            .iter()
+            .filter(|shingle_hash| state.shingle_hashes.contains(shingle_hash))
+            .copied()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TextModel for MemoryModel {
+    async fn learn(
+        &self,
+        member_id: u32,
+        channel_id: Option<u64>,
+        content: &str,
+    ) -> Result<bool, AddMemberWordsError> {
+        let tokens = textprocessing::message_tokens(content);
+        if tokens.is_empty() {
+            return Ok(false);
+        }
+        let mut state = self.lock_state();
+        let word_ids: Vec<i32> = tokens
+            .iter()
+            .map(|token| state.add_word(&token.key))
+            .collect();
+        // The begin and end of message tokens are never typed
+        for (word_id, token) in word_ids.iter().zip(&tokens[..tokens.len() - 1]).skip(1) {
+            *state
+                .forms
+                .entry((*word_id, token.sentence_start))
+                .or_default()
+                .entry(token.form.clone())
+                .or_insert(0) += 1;
+        }
+        state.members.entry(member_id).or_default().learn(&word_ids);
+        if let Some(channel_id) = channel_id {
+            state
+                .channels
+                .entry(channel_id)
+                .or_default()
+                .learn(&word_ids);
+        }
+        state
+            .shingle_hashes
+            .extend(originality::shingle_hashes(&word_ids));
+        Ok(true)
+    }
+
+    async fn generate(
+        &self,
+        personality: &Personality,
+        temperature: f64,
+        seed: Option<&str>,
+        seed_position: SeedPosition,
+    ) -> Result<String, MarkovFetchResultError> {
+        markov::generate_sentence(
+            self,
+            personality,
+            self.markov_order,
+            temperature,
+            seed,
+            seed_position,
+        )
+        .await
+    }
+
+    async fn generate_reply(
+        &self,
+        personality: &Personality,
+        temperature: f64,
+        seeds: &[String],
+    ) -> Result<String, MarkovFetchResultError> {
+        markov::generate_reply_sentence(self, personality, self.markov_order, temperature, seeds)
+            .await
+    }
+
+    async fn stats(&self, personality: &Personality) -> Result<ModelStats, MarkovFetchResultError> {
+        let begin_word_id = match self.sentinel_word_ids().await? {
+            Some((begin_word_id, _)) => begin_word_id,
+            None => return Ok(ModelStats::default()),
+        };
+        let state = self.lock_state();
+        let word_pairs: Vec<(i32, i32, u64)> = state
+            .models(personality)
+            .into_iter()
+            .flat_map(|(model, _)| model.word_pairs())
+            .collect();
+        Ok(ModelStats::from_word_pairs(&word_pairs, begin_word_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::client::textgen::markov::DEFAULT_TEMPERATURE;
+
+    /** A model that lets copies of learned messages through, so the chain itself can be tested. */
+    fn model(markov_order: u8) -> MemoryModel {
+        MemoryModel::new(markov_order, None)
+    }
+
+    async fn generate(
+        model: &MemoryModel,
+        personality: &Personality,
+        seed: Option<&str>,
+        seed_position: SeedPosition,
+    ) -> Result<String, MarkovFetchResultError> {
+        model
+            .generate(personality, DEFAULT_TEMPERATURE, seed, seed_position)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_generate_nothing_learned() {
+        let model = model(2);
+        match generate(
+            &model,
+            &Personality::Members(vec![(1, 1.0)]),
+            None,
+            SeedPosition::Start,
+        )
+        .await
+        {
+            Err(MarkovFetchResultError::NotEnoughWords(_, 0)) => {}
+            other => panic!("Expected not enough words, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_single_message() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        assert!(model
+            .learn(1, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn"));
+        assert_eq!(
+            "The cat sat on the mat",
+            generate(&model, &personality, None, SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_keeps_casing_and_punctuation() {
+        let model = model(3);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        model
+            .learn(1, None, "Hello there, General Kenobi! You are a bold one.")
+            .await
+            .expect("Should be able to learn");
+        assert_eq!(
+            "Hello there, General Kenobi! You are a bold one.",
+            generate(&model, &personality, None, SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_in_other_scripts() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        model
+            .learn(1, None, "Привет, как дела?")
+            .await
+            .expect("Should be able to learn");
+        model
+            .learn(1, None, "我爱你。")
+            .await
+            .expect("Should be able to learn");
+        // Seeds are case folded like everything that is learned
+        assert_eq!(
+            "Привет, как дела?",
+            generate(&model, &personality, Some("ПРИВЕТ"), SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+        assert_eq!(
+            "我爱你。",
+            generate(&model, &personality, Some("爱"), SeedPosition::Anywhere)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_emoji() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        model
+            .learn(1, None, "That was great <:pog:123> 😂")
+            .await
+            .expect("Should be able to learn");
+        assert_eq!(
+            "That was great <:pog:123> 😂",
+            generate(&model, &personality, None, SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_mention_placeholders() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        model
+            .learn(1, None, "Thanks <@123> for the help")
+            .await
+            .expect("Should be able to learn");
+        assert_eq!(
+            "Thanks <user:123> for the help",
+            generate(&model, &personality, None, SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_most_common_forms() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        for message in ["We all love NASA", "We all love NASA", "we all love nasa"] {
+            model
+                .learn(1, None, message)
+                .await
+                .expect("Should be able to learn");
+        }
+        // Every message is the same apart from how it was typed
+        assert_eq!(
+            "We all love NASA",
+            generate(&model, &personality, None, SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_too_short() {
+        let model = model(2);
+        model
+            .learn(1, None, "Hello there")
+            .await
+            .expect("Should be able to learn");
+        match generate(
+            &model,
+            &Personality::Members(vec![(1, 1.0)]),
+            None,
+            SeedPosition::Start,
+        )
+        .await
+        {
+            Err(MarkovFetchResultError::NotEnoughWords(_, 2)) => {}
+            other => panic!("Expected not enough words, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_from_seed() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        model
+            .learn(1, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn");
+        model
+            .learn(1, None, "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn");
+        assert_eq!(
+            "dog barked at birds",
+            generate(&model, &personality, Some("dog"), SeedPosition::Start)
+                .await
+                .expect("Should be able to generate a sentence")
+        );
+        assert_eq!(
+            "Some dog barked at birds",
+            generate(
+                &model,
+                &personality,
+                Some("barked at"),
+                SeedPosition::Anywhere
+            )
+            .await
+            .expect("Should be able to generate a sentence")
+        );
+        match generate(&model, &personality, Some("unicorn"), SeedPosition::Start).await {
+            Err(MarkovFetchResultError::SeedNotFound(seed)) => assert_eq!("unicorn", seed),
+            other => panic!("Expected the seed to be rejected, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_mashup_weights() {
+        let model = model(2);
+        model
+            .learn(1, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn");
+        model
+            .learn(2, None, "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn");
+        // A member with no weight never gets a word in
+        let personality = Personality::Members(vec![(1, 1.0), (2, 0.0)]);
+        for _ in 0..10 {
+            assert_eq!(
+                "The cat sat on the mat",
+                generate(&model, &personality, None, SeedPosition::Start)
+                    .await
+                    .expect("Should be able to generate a sentence")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_channel_and_server() {
+        let model = model(2);
+        model.add_channel(10, 100);
+        model.add_channel(10, 101);
+        model
+            .learn(1, Some(100), "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn");
+        model
+            .learn(2, Some(101), "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn");
+        match generate(
+            &model,
+            &Personality::Channel(100),
+            Some("dog"),
+            SeedPosition::Start,
+        )
+        .await
+        {
+            Err(MarkovFetchResultError::SeedNotFound(_)) => {}
+            other => panic!("Expected the seed to be rejected, got {:?}", other),
+        }
+        assert_eq!(
+            "dog barked at birds",
+            generate(
+                &model,
+                &Personality::Server(10),
+                Some("dog"),
+                SeedPosition::Start
+            )
+            .await
+            .expect("Should be able to generate a sentence")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_rejects_copies() {
+        let model = MemoryModel::default();
+        model
+            .learn(1, None, "The cat sat on the mat")
+            .await
+            .expect("Should be able to learn");
+        match generate(
+            &model,
+            &Personality::Members(vec![(1, 1.0)]),
+            None,
+            SeedPosition::Start,
+        )
+        .await
+        {
+            Err(MarkovFetchResultError::NotOriginal) => {}
+            other => panic!("Expected the copy to be rejected, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_reply() {
+        let model = model(2);
+        let personality = Personality::Members(vec![(1, 1.0)]);
+        model
+            .learn(1, None, "Some dog barked at birds")
+            .await
+            .expect("Should be able to learn");
+        let seeds = vec!["unicorn".to_owned(), "birds".to_owned()];
+        assert_eq!(
+            "Some dog barked at birds",
+            model
+                .generate_reply(&personality, DEFAULT_TEMPERATURE, &seeds)
+                .await
+                .expect("Should be able to generate a reply")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let model = model(2);
+        model.add_channel(10, 100);
+        model
+            .learn(1, Some(100), "The cat sat")
+            .await
+            .expect("Should be able to learn");
+        model
+            .learn(1, None, "The dog sat")
+            .await
+            .expect("Should be able to learn");
+        assert_eq!(
+            ModelStats {
+                messages: 2,
+                words: 4,
+                transitions: 8
+            },
+            model
+                .stats(&Personality::Members(vec![(1, 1.0)]))
+                .await
+                .expect("Should be able to get stats")
+        );
+        assert_eq!(
+            ModelStats {
+                messages: 1,
+                words: 3,
+                transitions: 4
+            },
+            model
+                .stats(&Personality::Server(10))
+                .await
+                .expect("Should be able to get stats")
+        );
+    }
+}
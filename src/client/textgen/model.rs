@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use std::collections::HashSet;
+
+use crate::client::database::entities::{Personality, SeedPosition};
+use crate::client::database::errors::{AddMemberWordsError, MarkovFetchResultError};
+use crate::client::textgen::textprocessing::SurfaceForms;
+
+/** How much has been learned about a personality. */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelStats {
+    /** The number of messages learned from. */
+    pub messages: u64,
+    /** The number of different words used. */
+    pub words: u64,
+    /** The number of times one word followed another, including the start and end of every message. */
+    pub transitions: u64,
+}
+
+// Only the in-memory model counts its stats this way
+#[cfg(test)]
+impl ModelStats {
+    /** Adds up `(word_id, next_word_id, frequency)` pairs. Messages are counted by how often they started, and every word but the begin of message token is followed by something. */
+    pub fn from_word_pairs(word_pairs: &[(i32, i32, u64)], begin_word_id: i32) -> ModelStats {
+        let mut words: Vec<i32> = word_pairs
+            .iter()
+            .map(|(word_id, _, _)| *word_id)
+            .filter(|word_id| *word_id != begin_word_id)
+            .collect();
+        words.sort_unstable();
+        words.dedup();
+        ModelStats {
+            messages: word_pairs
+                .iter()
+                .filter(|(word_id, _, _)| *word_id == begin_word_id)
+                .map(|(_, _, frequency)| frequency)
+                .sum(),
+            words: words.len() as u64,
+            transitions: word_pairs.iter().map(|(_, _, frequency)| frequency).sum(),
+        }
+    }
+}
+
+/// Learns how members talk and generates text that sounds like a member, a channel or a server.
+///
+/// Commands and events only go through this trait, so where the model is kept can change without touching them.
+#[async_trait]
+pub trait TextModel: Send + Sync {
+    /** Learns from a message sent by a member, and towards the channel it was sent in if there is one. Returns whether the message had anything to learn from. */
+    async fn learn(
+        &self,
+        member_id: u32,
+        channel_id: Option<u64>,
+        content: &str,
+    ) -> Result<bool, AddMemberWordsError>;
+
+    /** Generates a sentence that sounds like a personality, with as many words of context as its server uses. */
+    async fn generate(
+        &self,
+        personality: &Personality,
+        temperature: f64,
+        seed: Option<&str>,
+        seed_position: SeedPosition,
+    ) -> Result<String, MarkovFetchResultError>;
+
+    /** Generates a sentence around the first of the seeds the personality has used. Falls back to an unseeded sentence if it never used any of them. */
+    async fn generate_reply(
+        &self,
+        personality: &Personality,
+        temperature: f64,
+        seeds: &[String],
+    ) -> Result<String, MarkovFetchResultError>;
+
+    /** Returns how much has been learned about a personality. */
+    async fn stats(&self, personality: &Personality) -> Result<ModelStats, MarkovFetchResultError>;
+}
+
+/// The transitions and words that generation walks through, so the same Markov chain logic works on top of any model.
+#[async_trait]
+pub trait TransitionSource: Send + Sync {
+    /** Returns the word_ids of the begin and end of message tokens, if any message has been learned from yet. */
+    async fn sentinel_word_ids(&self) -> Result<Option<(i32, i32)>, MarkovFetchResultError>;
+
+    /** Returns the word_id of every word in the same order, or nothing if any of the words is unknown. Expects the keys words are stored by, as returned by `textprocessing::normalize`. */
+    async fn word_ids(&self, words: &[String]) -> Result<Option<Vec<i32>>, MarkovFetchResultError>;
+
+    /** Returns the words for a sequence of word_ids along with how they are usually typed, in the same order and including repeats. */
+    async fn words(&self, word_ids: &[i32]) -> Result<Vec<SurfaceForms>, MarkovFetchResultError>;
+
+    /** Returns every word a personality followed a context with, along with how often it did. */
+    async fn successors(
+        &self,
+        personality: &Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, MarkovFetchResultError>;
+
+    /** Returns every word a personality said right before a context, along with how often it did. */
+    async fn predecessors(
+        &self,
+        personality: &Personality,
+        context: &[i32],
+    ) -> Result<Vec<(i32, f64)>, MarkovFetchResultError>;
+
+    /** Returns the largest share of a generated sentence that may match learned messages, or nothing if they can't be checked for the personality. */
+    async fn originality_threshold(
+        &self,
+        personality: &Personality,
+    ) -> Result<Option<f64>, MarkovFetchResultError>;
+
+    /** Returns which of the shingle hashes of a generated sentence were seen in learned messages. */
+    async fn known_shingles(
+        &self,
+        personality: &Personality,
+        shingle_hashes: &[i64],
+    ) -> Result<HashSet<i64>, MarkovFetchResultError>;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_stats_from_word_pairs() {
+        // <s> the cat </s> and <s> the dog </s>, with 1 as <s> and 2 as </s>
+        let word_pairs = [(1, 3, 2), (3, 4, 1), (3, 5, 1), (4, 2, 1), (5, 2, 1)];
+        assert_eq!(
+            ModelStats {
+                messages: 2,
+                words: 3,
+                transitions: 6
+            },
+            ModelStats::from_word_pairs(&word_pairs, 1)
+        );
+    }
+}
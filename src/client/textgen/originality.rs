@@ -0,0 +1,156 @@
+use fnv::FnvHasher;
+use std::collections::HashSet;
+use std::hash::Hasher;
+
+/** The number of words in a shingle, counting the begin and end of message tokens. Longer than the largest context plus the word that follows it, since every run that long is copied by design. */
+pub const SHINGLE_SIZE: usize = 5;
+/** The largest share of a generated sentence's shingles that may come from learned messages in servers that haven't picked one. Keep in sync with the default in `server_settings`. */
+pub const DEFAULT_ORIGINALITY_THRESHOLD: f64 = 0.5;
+
+/** The most words in a row a generated sentence may copy from learned messages, counting the begin and end of message tokens, however little of the rest it copies. */
+pub const MAX_COPIED_RUN: usize = 8;
+
+/// Returns the hash of every run of `SHINGLE_SIZE` word_ids in a message in order, or of the whole message if it is shorter.
+///
+/// Expects the word_ids to include the begin and end of message tokens, so a copy of a whole short message is caught too.
+/// The hashes are stored, so they must not change between builds: FNV is used instead of the standard library's hasher.
+pub fn shingle_sequence(word_ids: &[i32]) -> Vec<i64> {
+    word_ids
+        .windows(SHINGLE_SIZE.min(word_ids.len()).max(1))
+        .map(|shingle| {
+            let mut hasher = FnvHasher::default();
+            for word_id in shingle {
+                hasher.write_i32(*word_id);
+            }
+            hasher.finish() as i64
+        })
+        .collect()
+}
+
+/** Returns the shingle hashes of a message without repeats, the way they are stored. */
+pub fn shingle_hashes(word_ids: &[i32]) -> Vec<i64> {
+    let mut hashes = shingle_sequence(word_ids);
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
+/** Returns whether a sentence is original enough, given its shingles in order and which of them were found in learned messages. It isn't if too large a share of its shingles is known, or if it copies a run of more than `MAX_COPIED_RUN` words. A threshold of 1 lets everything through. */
+pub fn is_original(shingles: &[i64], known: &HashSet<i64>, threshold: f64) -> bool {
+    if shingles.is_empty() || threshold >= 1.0 {
+        return true;
+    }
+    let distinct: HashSet<&i64> = shingles.iter().collect();
+    let known_share = distinct
+        .iter()
+        .filter(|shingle| known.contains(shingle))
+        .count() as f64
+        / distinct.len() as f64;
+    // Consecutive known shingles overlap, so a run of them covers SHINGLE_SIZE - 1 more words than there are shingles
+    let max_known_run = MAX_COPIED_RUN.saturating_sub(SHINGLE_SIZE - 1);
+    let longest_known_run = shingles
+        .iter()
+        .scan(0, |run, shingle| {
+            *run = if known.contains(shingle) { *run + 1 } else { 0 };
+            Some(*run)
+        })
+        .max()
+        .unwrap_or(0);
+    known_share <= threshold && longest_known_run <= max_known_run
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_shingle_hashes_count() {
+        assert_eq!(4, shingle_hashes(&[1, 2, 3, 4, 5, 6, 7, 8]).len());
+    }
+
+    #[test]
+    fn test_shingle_hashes_short_message() {
+        // A message shorter than a shingle is a shingle of its own
+        assert_eq!(1, shingle_hashes(&[1, 2, 3]).len());
+        assert!(shingle_hashes(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_shingle_hashes_stable() {
+        // Stored hashes must match the hashes of later sentences
+        assert_eq!(
+            shingle_hashes(&[1, 2, 3, 4, 5]),
+            shingle_hashes(&[1, 2, 3, 4, 5])
+        );
+        assert_ne!(
+            shingle_hashes(&[1, 2, 3, 4, 5]),
+            shingle_hashes(&[5, 4, 3, 2, 1])
+        );
+    }
+
+    #[test]
+    fn test_shingle_hashes_shared_run() {
+        let copied = shingle_hashes(&[1, 2, 3, 4, 5, 6]);
+        let sentence = shingle_hashes(&[9, 2, 3, 4, 5, 6, 9]);
+        let known = sentence.iter().filter(|hash| copied.contains(hash)).count();
+        assert_eq!(1, known);
+        assert_eq!(3, sentence.len());
+    }
+
+    #[test]
+    fn test_is_original() {
+        let shingles = [1, 2, 3, 4];
+        let known = |hashes: &[i64]| hashes.iter().copied().collect::<HashSet<i64>>();
+        assert!(is_original(
+            &shingles,
+            &known(&[1]),
+            DEFAULT_ORIGINALITY_THRESHOLD
+        ));
+        assert!(is_original(
+            &shingles,
+            &known(&[1, 3]),
+            DEFAULT_ORIGINALITY_THRESHOLD
+        ));
+        assert!(!is_original(
+            &shingles,
+            &known(&[1, 2, 3]),
+            DEFAULT_ORIGINALITY_THRESHOLD
+        ));
+        // A threshold of 1 turns the guard off
+        assert!(is_original(&shingles, &known(&[1, 2, 3, 4]), 1.0));
+        assert!(!is_original(&shingles, &known(&[1, 2, 3, 4]), 0.99));
+    }
+
+    #[test]
+    fn test_is_original_rejects_long_copied_run() {
+        // A 20 word sentence copying 10 words in a row of a real message
+        let copied: Vec<i32> = (100..110).collect();
+        let mut sentence: Vec<i32> = (1..6).collect();
+        sentence.extend(&copied);
+        sentence.extend(6..11);
+        let shingles = shingle_sequence(&sentence);
+        let known: HashSet<i64> = shingle_hashes(&copied).into_iter().collect();
+        let known_count = shingles
+            .iter()
+            .filter(|shingle| known.contains(shingle))
+            .count();
+        assert_eq!(16, shingles.len());
+        assert_eq!(6, known_count);
+        // Only about a third of the shingles are known, but the run is too long
+        assert!(!is_original(
+            &shingles,
+            &known,
+            DEFAULT_ORIGINALITY_THRESHOLD
+        ));
+        // A short enough run is fine
+        let known: HashSet<i64> = shingle_hashes(&copied[..MAX_COPIED_RUN])
+            .into_iter()
+            .collect();
+        assert!(is_original(
+            &shingles,
+            &known,
+            DEFAULT_ORIGINALITY_THRESHOLD
+        ));
+    }
+}
@@ -1,19 +1,329 @@
-use std::{borrow::Borrow, collections::HashMap};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
 
-/** Splits a vector of objects */
-pub fn vec_word_split(words: Vec<i32>) -> Vec<Vec<i32>> {
-    let mut returnable: Vec<Vec<i32>> = vec![];
-    // let words = words
-    //     .into_iter()
-    //     .map(|word| word.as_ref())
-    //     .collect::<Vec<&str>>();
-    let windows = words.windows(2);
-    let mut inc: u32 = 0;
-    for window in windows.into_iter() {
-        returnable.push(window.to_vec());
-        inc += 1;
+/** The largest number of words of context that transitions are learned for. */
+pub const MAX_MARKOV_ORDER: u8 = 3;
+/** The number of words of context used to generate text in servers that haven't picked one. Keep in sync with the default in `server_settings`. */
+pub const DEFAULT_MARKOV_ORDER: u8 = 2;
+
+/** Marks the start of a learned message. Pre-processing strips angle brackets, so no real word can collide with it. */
+pub const BEGIN_OF_MESSAGE: &str = "<s>";
+/** Marks the end of a learned message. */
+pub const END_OF_MESSAGE: &str = "</s>";
+/** Generated sentences shorter than this are generated again. */
+pub const MIN_SENTENCE_LENGTH: u8 = 4;
+/** Generated sentences are cut off at this many words, in case the end of message token is never drawn. */
+pub const MAX_SENTENCE_LENGTH: u8 = 20;
+/** How many times a sentence that ended too early or copied a learned message is generated again before giving up. */
+pub const GENERATION_ATTEMPTS: u8 = 5;
+
+/** Punctuation that ends a sentence, in any script. A run of it, like `?!` or `...`, is a single token. */
+pub const SENTENCE_END_PUNCTUATION: &[char] = &['.', '!', '?', '…', '。', '｡', '！', '？'];
+/** Punctuation that is kept as its own token, along with the punctuation that ends a sentence. */
+const CLAUSE_PUNCTUATION: &[char] = &[',', ';', ':', '、', '，', '；', '：'];
+/** Punctuation that opens a sentence or clause, as in Spanish, so it is attached to the word after it. */
+const OPENING_PUNCTUATION: &[char] = &['¿', '¡'];
+
+lazy_static! {
+    /** A custom emoji as discord sends it, like `<:name:id>`, or `<a:name:id>` if it is animated. */
+    pub static ref CUSTOM_EMOJI: Regex =
+        Regex::new(r"<a?:(?P<name>\w{2,32}):(?P<id>\d{1,20})>").expect("Should be valid regex");
+    /** A mention as discord sends it, of a user like `<@id>` or `<@!id>`, a role like `<@&id>` or a channel like `<#id>`. */
+    static ref MENTION: Regex =
+        Regex::new(r"<(?P<kind>@!?|@&|#)(?P<id>\d{1,20})>").expect("Should be valid regex");
+    /** The placeholder a mention is learned as, like `<user:id>`, `<role:id>` or `<channel:id>`. It is only ever rendered as a name, so it is harmless even when typed out by hand. */
+    static ref MENTION_PLACEHOLDER: Regex =
+        Regex::new(r"<(?P<kind>user|role|channel):(?P<id>\d{1,20})>").expect("Should be valid regex");
+    /** Tokens that are made of symbols, so they are kept whole through pre-processing and tokenizing: custom emoji and mention placeholders. */
+    pub static ref WHOLE_TOKEN: Regex =
+        Regex::new(r"<a?:\w{2,32}:\d{1,20}>|<(?:user|role|channel):\d{1,20}>")
+            .expect("Should be valid regex");
+}
+
+/** Something a learned message mentioned, by id. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mention {
+    User(u64),
+    Role(u64),
+    Channel(u64),
+}
+
+/** A token of a learned message, as it was typed and as it is matched. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /** What the token is stored and matched by, so differently typed versions of a word share their transitions. */
+    pub key: String,
+    /** The token as it was typed. */
+    pub form: String,
+    /** Whether the token started a sentence, where words are often capitalized no matter how they are usually typed. */
+    pub sentence_start: bool,
+}
+
+/** How a token is usually typed, at the start of a sentence and everywhere else. Either can be missing if it was never seen there, or if it was learned before surface forms were stored. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SurfaceForms {
+    pub key: String,
+    /** The most common way of typing the token at the start of a sentence. */
+    pub sentence_start: Option<String>,
+    /** The most common way of typing the token anywhere else. */
+    pub elsewhere: Option<String>,
+}
+
+impl SurfaceForms {
+    /** Returns the most common way of typing the token in a position, falling back to the other position and then to the key. */
+    pub fn form(&self, sentence_start: bool) -> &str {
+        let (preferred, fallback) = if sentence_start {
+            (&self.sentence_start, &self.elsewhere)
+        } else {
+            (&self.elsewhere, &self.sentence_start)
+        };
+        preferred
+            .as_deref()
+            .or(fallback.as_deref())
+            .unwrap_or(&self.key)
     }
-    returnable
+}
+
+/** Returns whether a token is punctuation that ends a sentence. */
+pub fn is_sentence_end(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| SENTENCE_END_PUNCTUATION.contains(&c))
+}
+
+/** Returns whether a token is punctuation that opens a sentence or clause. */
+fn is_opening(token: &str) -> bool {
+    token.chars().count() == 1 && token.starts_with(OPENING_PUNCTUATION)
+}
+
+/** Returns whether a token is punctuation rather than a word. */
+pub fn is_punctuation(token: &str) -> bool {
+    is_sentence_end(token)
+        || is_opening(token)
+        || (token.chars().count() == 1 && token.starts_with(CLAUSE_PUNCTUATION))
+}
+
+/** Returns whether a segment has a letter or number in it, in any script. */
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+/** Returns whether a segment is a unicode emoji. Skin tones, flags and emoji joined into one are a single segment already. */
+fn is_emoji(segment: &str) -> bool {
+    segment.starts_with(|c: char| {
+        matches!(c,
+            // Emoticons, pictographs, transport, flags and the other supplementary emoji blocks
+            '\u{1F000}'..='\u{1FAFF}'
+            // Miscellaneous technical symbols, arrows and shapes that have emoji versions
+            | '\u{2190}'..='\u{21FF}' | '\u{2300}'..='\u{23FF}' | '\u{25A0}'..='\u{27BF}'
+            | '\u{2900}'..='\u{297F}' | '\u{2B00}'..='\u{2BFF}'
+            | '©' | '®' | '‼' | '⁉' | '™' | 'ℹ' | '〰' | '〽' | '㊗' | '㊙'
+        )
+    })
+}
+
+/** Returns whether a character is from a script that is written without spaces between words, or is punctuation of one. */
+fn is_unspaced(c: char) -> bool {
+    matches!(c,
+        // Thai, Lao, Myanmar and Khmer
+        '\u{0E00}'..='\u{0EFF}' | '\u{1000}'..='\u{109F}' | '\u{1780}'..='\u{17FF}'
+        // CJK punctuation, Hiragana, Katakana and CJK ideographs
+        | '\u{3000}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{2FA1F}'
+        // Full width punctuation and half width Katakana
+        | '\u{FF00}'..='\u{FFEF}'
+    )
+}
+
+/** Returns the key a token is stored and matched by. Tokens are case folded, so `Straße`, `STRASSE` and `strasse` share a key, and compatibility characters like ligatures and full width letters are replaced by what they stand for. Emoji share a key whether or not they were typed with the selector that asks for the emoji version of a symbol. */
+pub fn normalize(token: &str) -> String {
+    token
+        .nfkc()
+        .filter(|c| *c != '\u{FE0F}')
+        .flat_map(char::to_lowercase)
+        .flat_map(|c| match c {
+            // The only characters whose case folding differs from their lowercase form after compatibility normalization, other than rare historic letters
+            'ß' => vec!['s', 's'],
+            'ς' => vec!['σ'],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/** Splits a pre-processed string into the tokens that get stored in the Markov data set, as they were typed, following the Unicode word boundary rules so that every script is split into words. Punctuation becomes its own token, unless it is inside a word like `3.5` or `It's`, and words joined by hyphens stay together. Every custom and unicode emoji and every mention placeholder is a token of its own, and other symbols are dropped.
+
+Scripts written without spaces have no word boundaries to find, so every character of them becomes a token. */
+pub fn tokenize(msg: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    // Word boundaries would split custom emoji and mention placeholders apart, so only the text between them is segmented
+    let mut text_start = 0;
+    for whole_token in WHOLE_TOKEN.find_iter(msg) {
+        tokens.extend(tokenize_text(&msg[text_start..whole_token.start()]));
+        tokens.push(whole_token.as_str().to_owned());
+        text_start = whole_token.end();
+    }
+    tokens.extend(tokenize_text(&msg[text_start..]));
+    tokens
+}
+
+/** Splits text without custom emoji or mention placeholders in it into tokens, as described for `tokenize`. */
+fn tokenize_text(msg: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    // What the previous segment was, since hyphens and runs of punctuation are joined to what came right before them
+    let mut after_word = false;
+    let mut after_sentence_end = false;
+    let mut hyphenated = false;
+    for segment in msg.split_word_bounds() {
+        let joins = match tokens.last_mut() {
+            Some(last) if (hyphenated && is_word(segment)) => {
+                last.push('-');
+                Some(last)
+            }
+            Some(last) if (after_sentence_end && is_sentence_end(segment)) => Some(last),
+            _ => None,
+        };
+        match joins {
+            Some(last) => last.push_str(segment),
+            None if is_word(segment) || is_punctuation(segment) || is_emoji(segment) => {
+                tokens.push(segment.to_owned())
+            }
+            None => {}
+        }
+        hyphenated = after_word && segment == "-";
+        after_word = is_word(segment);
+        after_sentence_end = is_sentence_end(segment);
+    }
+    tokens
+}
+
+/** Pre-processes and tokenizes a message to be learned from, between the begin and end of message tokens so generated sentences can start and end the same way. Returns nothing if the message has no tokens. */
+pub fn message_tokens(content: &str) -> Vec<Token> {
+    let forms = tokenize(&crate::client::textgen::markov::filter_string_pre_processing(content));
+    if forms.is_empty() {
+        return vec![];
+    }
+    let sentinel = |key: &str| Token {
+        key: key.to_owned(),
+        form: key.to_owned(),
+        sentence_start: false,
+    };
+    let mut tokens = vec![sentinel(BEGIN_OF_MESSAGE)];
+    let mut sentence_start = true;
+    for form in forms {
+        let next_sentence_start = is_sentence_end(&form) || (sentence_start && is_opening(&form));
+        tokens.push(Token {
+            key: normalize(&form),
+            form,
+            sentence_start,
+        });
+        sentence_start = next_sentence_start;
+    }
+    tokens.push(sentinel(END_OF_MESSAGE));
+    tokens
+}
+
+/** Joins generated tokens back into text, typing each one the way it is most commonly typed in its position. Punctuation is attached to the word before it, opening punctuation to the word after it, and scripts written without spaces are joined up again. */
+pub fn render(tokens: &[SurfaceForms]) -> String {
+    let mut text = String::new();
+    let mut sentence_start = true;
+    let mut after_opening = false;
+    for token in tokens {
+        let form = token.form(sentence_start);
+        let unspaced = text.ends_with(is_unspaced) && form.starts_with(is_unspaced);
+        let attached = is_punctuation(&token.key) && !is_opening(&token.key);
+        if !text.is_empty() && !attached && !after_opening && !unspaced {
+            text.push(' ');
+        }
+        text.push_str(form);
+        after_opening = is_opening(&token.key);
+        sentence_start = is_sentence_end(&token.key) || (sentence_start && after_opening);
+    }
+    text
+}
+
+/** Replaces the user, role and channel mentions in a message with placeholders, so that who or what was mentioned is learned without the mention itself. */
+pub fn mention_placeholders(msg: &str) -> String {
+    MENTION
+        .replace_all(msg, |captures: &Captures| {
+            let kind = match &captures["kind"] {
+                "@&" => "role",
+                "#" => "channel",
+                _ => "user",
+            };
+            format!("<{}:{}>", kind, &captures["id"])
+        })
+        .into_owned()
+}
+
+/** Replaces the mention placeholders in generated text with names, so that sending it can't ping anyone. `name` is asked for the name of everything that was mentioned. */
+pub fn render_mentions(text: &str, name: impl Fn(Mention) -> String) -> String {
+    MENTION_PLACEHOLDER
+        .replace_all(text, |captures: &Captures| {
+            let id: u64 = match captures["id"].parse() {
+                Ok(id) => id,
+                Err(_) => return String::new(),
+            };
+            name(match &captures["kind"] {
+                "role" => Mention::Role(id),
+                "channel" => Mention::Channel(id),
+                _ => Mention::User(id),
+            })
+        })
+        .into_owned()
+}
+
+/** Returns the user_id of every user mentioned in generated text, without repeats, so their names can be looked up before it is rendered. */
+pub fn mentioned_users(text: &str) -> Vec<u64> {
+    MENTION_PLACEHOLDER
+        .captures_iter(text)
+        .filter(|captures| &captures["kind"] == "user")
+        .filter_map(|captures| captures["id"].parse().ok())
+        .unique()
+        .collect()
+}
+
+/** Replaces the custom emoji in generated text that can't be used with their name between colons, the way discord shows custom emoji it can't find. `usable` is asked about the id of every custom emoji. */
+pub fn fallback_custom_emoji(text: &str, usable: impl Fn(u64) -> bool) -> String {
+    CUSTOM_EMOJI
+        .replace_all(text, |captures: &Captures| match captures["id"].parse() {
+            Ok(emoji_id) if usable(emoji_id) => captures[0].to_owned(),
+            _ => format!(":{}:", &captures["name"]),
+        })
+        .into_owned()
+}
+
+/** Common words that say little about what a message is about, so they aren't used to seed replies. */
+pub const STOP_WORDS: &[&str] = &[
+    "a", "about", "all", "am", "an", "and", "are", "as", "at", "be", "but", "by", "can", "do",
+    "for", "from", "get", "got", "had", "has", "have", "he", "her", "him", "his", "how", "i", "if",
+    "im", "in", "is", "it", "its", "just", "me", "my", "no", "not", "of", "on", "or", "she", "so",
+    "that", "the", "their", "them", "then", "there", "they", "this", "to", "up", "was", "we",
+    "what", "when", "who", "why", "will", "with", "you", "your",
+];
+
+/** Returns the keys of the words of a pre-processed message that say something about what it is about, longest first, without repeats. Emoji say how someone feels rather than what about, and mentions are mostly of the bot being talked to, so both are left out. */
+pub fn content_words(msg: &str) -> Vec<String> {
+    let mut words: Vec<String> = tokenize(msg)
+        .iter()
+        .filter(|token| !is_punctuation(token))
+        .filter(|token| !WHOLE_TOKEN.is_match(token) && !is_emoji(token))
+        .map(|token| normalize(token))
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect();
+    // Longer words tend to be more specific
+    words.sort_by_key(|word| std::cmp::Reverse(word.chars().count()));
+    let mut seen = std::collections::HashSet::new();
+    words.retain(|word| seen.insert(word.clone()));
+    words
+}
+
+/** Splits a vector of objects into overlapping windows of `order` words of context followed by the word that came next. */
+pub fn vec_word_split(words: Vec<i32>, order: usize) -> Vec<Vec<i32>> {
+    words
+        .windows(order + 1)
+        .map(|window| window.to_vec())
+        .collect()
 }
 
 #[cfg(test)]
@@ -21,34 +331,290 @@ mod tests {
 
     use super::*;
 
-    // #[test]
-    // fn test_split_strings_even_words() {
-    //     let test_input = vec![
-    //         "hi".to_owned(),
-    //         "my".to_owned(),
-    //         "name".to_owned(),
-    //         "minecraft".to_owned(),
-    //     ];
-    //     let mut correct_map = HashMap::new();
-    //     correct_map.insert(0u32, vec!["hi".to_owned(), "my".to_owned()]);
-    //     correct_map.insert(1u32, vec!["my".to_owned(), "name".to_owned()]);
-    //     correct_map.insert(2u32, vec!["name".to_owned(), "minecraft".to_owned()]);
-    //     assert_eq!(correct_map, vec_word_split(test_input));
-    // }
-    // #[test]
-    // fn test_split_strings_odd_words() {
-    //     let test_input = vec!["hi".to_owned(), "my".to_owned(), "name".to_owned()];
-    //     let mut correct_map = HashMap::new();
-    //     correct_map.insert(0u32, vec!["hi".to_owned(), "my".to_owned()]);
-    //     correct_map.insert(1u32, vec!["my".to_owned(), "name".to_owned()]);
-    //     assert_eq!(correct_map, vec_word_split(test_input));
-    // }
-    // #[test]
-    // fn test_split_strings_odd_words() {
-    //     let test_input = vec!["hi", "my", "name"];
-    //     let mut correct_map = HashMap::new();
-    //     correct_map.insert(0u32, vec!["hi", "my"]);
-    //     correct_map.insert(1u32, vec!["my", "name"]);
-    //     assert_eq!(correct_map, vec_word_split(test_input));
-    // }
+    #[test]
+    fn test_tokenize_punctuation() {
+        assert_eq!(
+            vec!["Hello", "there", ",", "friend", "?!"],
+            tokenize("Hello there, friend?!")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_punctuation_inside_words() {
+        assert_eq!(
+            vec![
+                "It's",
+                "3.5",
+                "or",
+                "3,5",
+                "for",
+                "well-known",
+                "people",
+                "..."
+            ],
+            tokenize("It's 3.5 or 3,5 for well-known people - - ...")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_accented_and_cyrillic() {
+        assert_eq!(
+            vec!["¿", "Cómo", "estás", ",", "señor", "?"],
+            tokenize("¿Cómo estás, señor?")
+        );
+        assert_eq!(
+            vec!["Привет", ",", "как", "дела", "?!"],
+            tokenize("Привет, как дела?!")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unspaced_scripts() {
+        assert_eq!(vec!["我", "爱", "你", "。"], tokenize("我爱你。"));
+        assert_eq!(
+            vec!["カタカナ", "と", "ひ", "ら", "が", "な"],
+            tokenize("カタカナとひらがな")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_custom_emoji() {
+        assert_eq!(
+            vec!["gg", "<:pog:123456>", "nice", "<a:party_blob:42>", "!"],
+            tokenize("gg <:pog:123456> nice<a:party_blob:42>!")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unicode_emoji() {
+        assert_eq!(
+            vec!["love", "it", "😂", "😂", "👍🏽", "👨‍👩‍👧", "🇯🇵", "❤️"],
+            tokenize("love it 😂😂 👍🏽 👨‍👩‍👧 🇯🇵 ❤️")
+        );
+    }
+
+    #[test]
+    fn test_normalize_emoji_presentation() {
+        assert_eq!(normalize("❤"), normalize("❤️"));
+    }
+
+    #[test]
+    fn test_fallback_custom_emoji() {
+        assert_eq!(
+            "hi <:pog:1> :dance:",
+            fallback_custom_emoji("hi <:pog:1> <a:dance:2>", |emoji_id| emoji_id == 1)
+        );
+    }
+
+    #[test]
+    fn test_mention_placeholders() {
+        assert_eq!(
+            "hi <user:123> <user:124>, <role:456> in <channel:789>",
+            mention_placeholders("hi <@123> <@!124>, <@&456> in <#789>")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_mention_placeholders() {
+        assert_eq!(
+            vec!["thanks", "<user:123>", "!"],
+            tokenize("thanks <user:123>!")
+        );
+    }
+
+    #[test]
+    fn test_render_mentions() {
+        let name = |mention: Mention| match mention {
+            Mention::User(123) => "@alice".to_owned(),
+            Mention::Role(_) => "@mods".to_owned(),
+            _ => "#general".to_owned(),
+        };
+        assert_eq!(
+            "ask @alice or @mods in #general",
+            render_mentions("ask <user:123> or <role:456> in <channel:789>", name)
+        );
+    }
+
+    #[test]
+    fn test_mentioned_users() {
+        assert_eq!(
+            vec![123, 456],
+            mentioned_users("<user:123> and <role:789> told <user:456> and <user:123>")
+        );
+    }
+
+    #[test]
+    fn test_normalize_case_folds() {
+        assert_eq!("strasse", normalize("Straße"));
+        assert_eq!(normalize("STRASSE"), normalize("straße"));
+        assert_eq!("привет", normalize("ПРИВЕТ"));
+        // A final sigma is folded like any other sigma
+        assert_eq!(normalize("ΟΔΟΣ"), normalize("οδος"));
+        assert_eq!("éclair", normalize("ÉCLAIR"));
+    }
+
+    #[test]
+    fn test_normalize_compatibility_characters() {
+        assert_eq!("file", normalize("ﬁle"));
+        assert_eq!("abc", normalize("ＡＢＣ"));
+    }
+
+    #[test]
+    fn test_message_tokens_compose_accents() {
+        // e followed by a combining acute accent
+        let tokens = message_tokens("Cafe\u{301} time");
+        assert_eq!("Café", tokens[1].form);
+        assert_eq!("café", tokens[1].key);
+    }
+
+    #[test]
+    fn test_message_tokens_sentence_start_after_opening_punctuation() {
+        let tokens = message_tokens("¿Qué tal?");
+        assert_eq!(
+            vec![false, true, true, false, false, false],
+            tokens
+                .iter()
+                .map(|token| token.sentence_start)
+                .collect::<Vec<bool>>()
+        );
+    }
+
+    #[test]
+    fn test_message_tokens_sentence_starts() {
+        let tokens = message_tokens("Hi. It's me");
+        assert_eq!(
+            vec![BEGIN_OF_MESSAGE, "hi", ".", "it's", "me", END_OF_MESSAGE],
+            tokens
+                .iter()
+                .map(|token| token.key.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            vec![false, true, false, true, false, false],
+            tokens
+                .iter()
+                .map(|token| token.sentence_start)
+                .collect::<Vec<bool>>()
+        );
+        assert_eq!("It's", tokens[3].form);
+    }
+
+    #[test]
+    fn test_message_tokens_nothing_to_learn() {
+        assert!(message_tokens("https://example.com ($)").is_empty());
+    }
+
+    #[test]
+    fn test_surface_forms_fall_back() {
+        let forms = SurfaceForms {
+            key: "nasa".to_owned(),
+            sentence_start: None,
+            elsewhere: Some("NASA".to_owned()),
+        };
+        assert_eq!("NASA", forms.form(true));
+        assert_eq!("NASA", forms.form(false));
+        let unknown = SurfaceForms {
+            key: "cat".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!("cat", unknown.form(true));
+    }
+
+    #[test]
+    fn test_render() {
+        let forms = |key: &str, sentence_start: &str, elsewhere: &str| SurfaceForms {
+            key: key.to_owned(),
+            sentence_start: Some(sentence_start.to_owned()),
+            elsewhere: Some(elsewhere.to_owned()),
+        };
+        let tokens = [
+            forms("the", "The", "the"),
+            forms("cat", "Cat", "cat"),
+            forms(",", ",", ","),
+            forms("the", "The", "the"),
+            forms("dog", "Dog", "dog"),
+            forms(".", ".", "."),
+            forms("the", "The", "the"),
+            forms("end", "End", "end"),
+        ];
+        assert_eq!("The cat, the dog. The end", render(&tokens));
+    }
+
+    #[test]
+    fn test_render_scripts() {
+        let forms = |form: &str| SurfaceForms {
+            key: normalize(form),
+            sentence_start: None,
+            elsewhere: Some(form.to_owned()),
+        };
+        let spanish = ["¿", "Cómo", "estás", "?"].map(forms);
+        assert_eq!("¿Cómo estás?", render(&spanish));
+        let chinese = ["我", "爱", "你", "。", "你", "好"].map(forms);
+        assert_eq!("我爱你。你好", render(&chinese));
+        let mixed = ["我", "爱", "Rust", "!"].map(forms);
+        assert_eq!("我爱 Rust!", render(&mixed));
+    }
+
+    #[test]
+    fn test_content_words_skip_punctuation() {
+        assert_eq!(vec!["pizza", "love"], content_words("I love Pizza!"));
+    }
+
+    #[test]
+    fn test_content_words() {
+        assert_eq!(
+            vec!["pizza", "like"],
+            content_words("what do you like about pizza")
+        );
+    }
+
+    #[test]
+    fn test_content_words_skip_emoji() {
+        assert_eq!(
+            vec!["pizza", "love"],
+            content_words("love pizza 😂 <:pog:123456> <a:party_blob:654321>")
+        );
+    }
+
+    #[test]
+    fn test_content_words_skip_mentions() {
+        assert_eq!(
+            vec!["think", "pizza"],
+            content_words(
+                "<user:123456789012345678> what do you think about pizza <role:42> <channel:7>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_content_words_no_repeats() {
+        assert_eq!(
+            vec!["cats", "dogs"],
+            content_words("cats and dogs and cats")
+        );
+    }
+
+    #[test]
+    fn test_split_words_first_order() {
+        let test_input = vec![1, 2, 3, 4];
+        assert_eq!(
+            vec![vec![1, 2], vec![2, 3], vec![3, 4]],
+            vec_word_split(test_input, 1)
+        );
+    }
+
+    #[test]
+    fn test_split_words_second_order() {
+        let test_input = vec![1, 2, 3, 4];
+        assert_eq!(
+            vec![vec![1, 2, 3], vec![2, 3, 4]],
+            vec_word_split(test_input, 2)
+        );
+    }
+
+    #[test]
+    fn test_split_words_too_few_words() {
+        let test_input = vec![1, 2];
+        assert!(vec_word_split(test_input, 3).is_empty());
+    }
 }
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use crate::client::textgen::textprocessing;
+
+/** A rough estimate of the memory a single transition takes up in a model, besides its context. Both the forward and the reverse table hold a copy. */
+const TRANSITION_BYTES: usize = 96;
+
+/** Adds up how often each word follows or precedes a sequence of words. */
+type TransitionTable = HashMap<Vec<i32>, HashMap<i32, u64>>;
+
+/// The transitions of a member or a channel, kept in memory so sentences can be generated without a query for every word.
+///
+/// Transitions are kept for every order, from single words up to `MAX_MARKOV_ORDER`, in both directions.
+#[derive(Debug, Default)]
+pub struct TransitionModel {
+    /** How often each word followed a context. */
+    successors: TransitionTable,
+    /** How often each word came right before a sequence of words, which is the rest of the context followed by the next word. */
+    predecessors: TransitionTable,
+    /** A rough estimate of the memory the model takes up. */
+    approximate_bytes: usize,
+}
+
+impl TransitionModel {
+    /** Counts a context being followed by a word, as often as given. */
+    pub fn add_transition(&mut self, context: &[i32], next_word_id: i32, frequency: u64) {
+        let (previous_word_id, rest) = match context.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let successors = self.successors.entry(context.to_vec()).or_default();
+        if !successors.contains_key(&next_word_id) {
+            self.approximate_bytes += 2 * (TRANSITION_BYTES + context.len() * 4);
+        }
+        *successors.entry(next_word_id).or_insert(0) += frequency;
+        let mut following = rest.to_vec();
+        following.push(next_word_id);
+        *self
+            .predecessors
+            .entry(following)
+            .or_default()
+            .entry(*previous_word_id)
+            .or_insert(0) += frequency;
+    }
+
+    /** Counts every transition in a learned sequence of word_ids, for every order, the same way they are stored in the database. */
+    pub fn learn(&mut self, word_ids: &[i32]) {
+        for order in 1..=textprocessing::MAX_MARKOV_ORDER as usize {
+            for insertable in textprocessing::vec_word_split(word_ids.to_vec(), order) {
+                self.add_transition(&insertable[..order], insertable[order], 1);
+            }
+        }
+    }
+
+    /** Returns every word that followed a context, along with how often it did. */
+    pub fn successors(&self, context: &[i32]) -> Vec<(i32, u64)> {
+        Self::lookup(&self.successors, context)
+    }
+
+    /** Returns every word that came right before a sequence of words, along with how often it did. */
+    pub fn predecessors(&self, following: &[i32]) -> Vec<(i32, u64)> {
+        Self::lookup(&self.predecessors, following)
+    }
+
+    fn lookup(table: &TransitionTable, key: &[i32]) -> Vec<(i32, u64)> {
+        table
+            .get(key)
+            .map(|words| {
+                words
+                    .iter()
+                    .map(|(word_id, frequency)| (*word_id, *frequency))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /** Returns every pair of words that followed each other, along with how often they did. */
+    // Only the in-memory model counts its stats this way
+    #[cfg(test)]
+    pub fn word_pairs(&self) -> Vec<(i32, i32, u64)> {
+        self.successors
+            .iter()
+            .filter(|(context, _)| context.len() == 1)
+            .flat_map(|(context, words)| {
+                words
+                    .iter()
+                    .map(|(next_word_id, frequency)| (context[0], *next_word_id, *frequency))
+            })
+            .collect()
+    }
+
+    /** Returns a rough estimate of the memory the model takes up. */
+    pub fn approximate_bytes(&self) -> usize {
+        self.approximate_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn model(word_ids: &[i32]) -> TransitionModel {
+        let mut model = TransitionModel::default();
+        model.learn(word_ids);
+        model
+    }
+
+    #[test]
+    fn test_model_successors() {
+        let model = model(&[1, 2, 3, 2, 4]);
+        let mut successors = model.successors(&[2]);
+        successors.sort();
+        assert_eq!(vec![(3, 1), (4, 1)], successors);
+        assert_eq!(vec![(4, 1)], model.successors(&[3, 2]));
+        assert_eq!(vec![(2, 1)], model.successors(&[1, 2, 3]));
+        assert!(model.successors(&[4]).is_empty());
+    }
+
+    #[test]
+    fn test_model_predecessors() {
+        let model = model(&[1, 2, 3, 2, 4]);
+        let mut predecessors = model.predecessors(&[2]);
+        predecessors.sort();
+        assert_eq!(vec![(1, 1), (3, 1)], predecessors);
+        assert_eq!(vec![(3, 1)], model.predecessors(&[2, 4]));
+        assert_eq!(vec![(1, 1)], model.predecessors(&[2, 3, 2]));
+        assert!(model.predecessors(&[1]).is_empty());
+    }
+
+    #[test]
+    fn test_model_frequencies_add_up() {
+        let mut model = model(&[1, 2]);
+        model.learn(&[1, 2]);
+        model.add_transition(&[1], 2, 3);
+        assert_eq!(vec![(2, 5)], model.successors(&[1]));
+        assert_eq!(vec![(1, 5)], model.predecessors(&[2]));
+    }
+
+    #[test]
+    fn test_model_word_pairs() {
+        let mut word_pairs = model(&[1, 2, 3, 2, 3]).word_pairs();
+        word_pairs.sort();
+        assert_eq!(vec![(1, 2, 1), (2, 3, 2), (3, 2, 1)], word_pairs);
+    }
+}
@@ -0,0 +1,108 @@
+use serenity::http::{Http, HttpError, StatusCode};
+use serenity::model::prelude::*;
+use serenity::prelude::Mentionable;
+
+use crate::client::commands::Error;
+use crate::client::database::entities::ChannelWebhook;
+use crate::client::database::interface::DbInterface;
+
+/** The name the bot gives the webhooks it makes. Every post overrides it with the name of whoever is imitated. */
+pub const WEBHOOK_NAME: &str = "tripleA mimic";
+/** Added to the name of an imitated member, so nobody mistakes generated text for something they really said. */
+pub const IMPERSONATION_SUFFIX: &str = " (bot)";
+/** The longest name discord allows a webhook post to have. */
+pub const MAX_WEBHOOK_USERNAME_CHARS: usize = 80;
+
+/** The name to post under when imitating a member, which always ends with the suffix marking it as the bot. */
+pub fn impersonation_name(display_name: &str) -> String {
+    let max_name_chars = MAX_WEBHOOK_USERNAME_CHARS - IMPERSONATION_SUFFIX.chars().count();
+    let name: String = display_name.trim().chars().take(max_name_chars).collect();
+    format!("{}{}", name.trim_end(), IMPERSONATION_SUFFIX)
+}
+
+/** Whether discord answered that the requested thing does not exist (anymore). */
+fn is_not_found(why: &serenity::Error) -> bool {
+    match why {
+        serenity::Error::Http(why) => matches!(
+            why.as_ref(),
+            HttpError::UnsuccessfulRequest(response) if response.status_code == StatusCode::NOT_FOUND
+        ),
+        _ => false,
+    }
+}
+
+/** Returns the webhook the bot posts through in a channel. It is made the first time it is needed, and made again if someone deleted it. Expects the channel to be tracked. */
+pub async fn channel_webhook(
+    http: &Http,
+    db: &DbInterface,
+    channel_id: ChannelId,
+) -> Result<Webhook, Error> {
+    if let Some(stored) = db.fetch_channel_webhook(channel_id.0).await? {
+        match Webhook::from_id_with_token(http, stored.webhook_id, &stored.token).await {
+            Ok(webhook) => return Ok(webhook),
+            Err(why) if is_not_found(&why) => {}
+            Err(why) => return Err(why.into()),
+        }
+    }
+    let webhook = match channel_id.create_webhook(http, WEBHOOK_NAME).await {
+        Ok(webhook) => webhook,
+        Err(why) => {
+            return Err(format!(
+                "I couldn't make a webhook in {} to post through ({}). Make sure I have the Manage Webhooks permission there!",
+                channel_id.mention(),
+                why
+            )
+            .into())
+        }
+    };
+    let token = webhook
+        .token
+        .clone()
+        .ok_or("Discord didn't give me the token of the webhook I just made!")?;
+    db.set_channel_webhook(
+        channel_id.0,
+        &ChannelWebhook {
+            webhook_id: webhook.id.0,
+            token,
+        },
+    )
+    .await?;
+    Ok(webhook)
+}
+
+/** Deletes the webhook the bot made in a channel, if it made one, and then forgets it. A webhook someone already deleted is simply forgotten. If discord refuses to delete it, it is remembered so deleting it can be tried again. */
+pub async fn remove_channel_webhook(
+    http: &Http,
+    db: &DbInterface,
+    channel_id: ChannelId,
+) -> Result<(), Error> {
+    if let Some(stored) = db.fetch_channel_webhook(channel_id.0).await? {
+        match http
+            .delete_webhook_with_token(stored.webhook_id, &stored.token)
+            .await
+        {
+            Err(why) if !is_not_found(&why) => return Err(why.into()),
+            _ => {}
+        }
+        db.remove_channel_webhook(channel_id.0).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impersonation_name_is_marked() {
+        assert_eq!("Alice (bot)", impersonation_name("Alice"));
+        assert_eq!("Bob (bot)", impersonation_name("  Bob  "));
+    }
+
+    #[test]
+    fn test_impersonation_name_fits_discord_limit() {
+        let name = impersonation_name(&"é".repeat(200));
+        assert_eq!(MAX_WEBHOOK_USERNAME_CHARS, name.chars().count());
+        assert!(name.ends_with(IMPERSONATION_SUFFIX));
+    }
+}
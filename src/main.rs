@@ -1,69 +1,184 @@
 mod client;
-use client::commands::general::MIMIC_COMMAND;
-use client::commands::owner::STOP_COMMAND;
+use client::commands::{game, general, on_error, owner, utils, Error};
+use client::data::Data;
+use client::database::entities::{Personality, ReplyPersona};
+use client::database::errors::MarkovFetchResultError;
 use client::database::interface::DbInterface;
-use std::collections::HashSet;
+use client::textgen::markov::{self, DEFAULT_TEMPERATURE};
+use client::textgen::textprocessing;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::sync::Arc;
+use std::time::Instant;
 
-use serenity::async_trait;
-use serenity::client::bridge::gateway::ShardManager;
-use serenity::framework::standard::macros::group;
-use serenity::framework::StandardFramework;
-use serenity::http::Http;
+use serenity::model::channel::Message;
 use serenity::model::event::ResumedEvent;
 use serenity::model::gateway::Ready;
+use serenity::model::id::{GuildId, UserId};
 use serenity::prelude::*;
 use tracing::{error, info};
 
-// Manages shards. Basically use this to kill bot.
-pub struct ShardManagerContainer;
-
-// Here we endow ShardManagerContainer with the ability to be stored in a type map (for serenity contexts)
-impl TypeMapKey for ShardManagerContainer {
-    type Value = Arc<Mutex<ShardManager>>;
-}
+/// The prefix for text commands. Every command is also available as a slash command.
+pub const PREFIX: &str = "triple ";
 
 // Here is our event handler.
 struct Handler;
 
-// Here we implement basic functionality for event commands. TODO: Implement more functionality by calling async functions from submodules of client. (ex: storing message)
-#[async_trait]
-impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+// Here we implement basic functionality for event commands. Messages from tracked members in tracked channels are learned from here, and the bot answers when it is talked to or chimes in on its own.
+impl Handler {
+    async fn ready(_: &Context, ready: &Ready) {
         info!("Connected as {}", ready.user.name);
     }
 
-    async fn resume(&self, _: Context, _: ResumedEvent) {
+    async fn resume(_: &Context, _: &ResumedEvent) {
         info!("Resumed");
     }
 
-    async fn message(&self, ctx: Context, msg: serenity::model::channel::Message) {
-        if !msg.author.bot {
+    async fn message(ctx: &Context, msg: &Message, bot_id: UserId, data: &Data) {
+        // Don't learn from bots, direct messages or command invocations
+        if msg.author.bot || msg.content.starts_with(PREFIX) {
+            return;
+        }
+        let guild_id = match msg.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+        // Answer in character when talked to. This happens before learning from the message, so the answer can't just repeat it.
+        let replied_to_bot = msg
+            .referenced_message
+            .as_ref()
+            .is_some_and(|referenced| referenced.author.id == bot_id);
+        // Guesses in a round of "Who said it?" are often replies to the round, so the bot keeps quiet until it's over
+        let playing = data.games.lock().await.contains(&msg.channel_id.0);
+        if !playing {
+            if msg.mentions_user_id(bot_id) || replied_to_bot {
+                if let Err(why) = Handler::reply(ctx, msg, guild_id, data).await {
+                    error!("Could not reply to message {}: {}", msg.id, why);
+                }
+            } else if let Err(why) = Handler::chime_in(ctx, msg, guild_id, data).await {
+                error!("Could not chime in after message {}: {}", msg.id, why);
+            }
+        }
+        if let Err(why) = Handler::learn(msg, guild_id, data).await {
+            error!("Could not learn from message {}: {}", msg.id, why);
+        }
+    }
+
+    /// Learns from a message if it was sent by a tracked member in a tracked channel.
+    async fn learn(msg: &Message, guild_id: GuildId, data: &Data) -> Result<(), Error> {
+        if let Some(member_id) = data
+            .db
+            .fetch_learning_member(guild_id.0, msg.channel_id.0, msg.author.id.0)
+            .await?
+        {
+            data.model
+                .learn(member_id, Some(msg.channel_id.0), &msg.content)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Replies to a message using its content words as seeds, sounding like the server's reply persona.
+    async fn reply(
+        ctx: &Context,
+        msg: &Message,
+        guild_id: GuildId,
+        data: &Data,
+    ) -> Result<(), Error> {
+        let seeds =
+            textprocessing::content_words(&markov::filter_string_pre_processing(&msg.content));
+        if let Some(sentence) = Handler::persona_sentence(msg, guild_id, data, &seeds).await? {
+            let sentence = utils::sendable(ctx, guild_id, &sentence).await;
             msg.channel_id
-                .say(
-                    &ctx.http,
-                    msg.content_safe(&ctx.cache)
-                        .chars()
-                        .rev()
-                        .collect::<String>(),
-                )
-                .await
-                .expect("Should be able to speak");
-        } else {
+                .send_message(ctx, |m| {
+                    m.content(sentence)
+                        .reference_message(msg)
+                        .allowed_mentions(|mentions| mentions.empty_parse().replied_user(false))
+                })
+                .await?;
         }
+        Ok(())
+    }
+
+    /// Posts a message of the bot's own every so often in channels that are set up for it, seeded from the latest messages in the channel.
+    async fn chime_in(
+        ctx: &Context,
+        msg: &Message,
+        guild_id: GuildId,
+        data: &Data,
+    ) -> Result<(), Error> {
+        let settings = match data.db.fetch_chime_settings(msg.channel_id.0).await? {
+            Some(settings) => settings,
+            None => return Ok(()),
+        };
+        let seeds = {
+            let mut chime = data.chime.lock().await;
+            let state = chime.entry(msg.channel_id.0).or_default();
+            if !state.record_message(
+                &msg.content,
+                &settings,
+                Instant::now(),
+                &mut rand::thread_rng(),
+            ) {
+                return Ok(());
+            }
+            state.seeds()
+        };
+        if let Some(sentence) = Handler::persona_sentence(msg, guild_id, data, &seeds).await? {
+            let sentence = utils::sendable(ctx, guild_id, &sentence).await;
+            msg.channel_id
+                .send_message(ctx, |m| {
+                    m.content(sentence)
+                        .allowed_mentions(|mentions| mentions.empty_parse())
+                })
+                .await?;
+        }
+        Ok(())
     }
-}
 
-// Here we store commands in the appropriate groups.
-#[group]
-#[commands(mimic)]
-struct General;
+    /// Generates a sentence that sounds like the server's reply persona, starting from the first seed it can. Returns nothing if too little has been learned yet.
+    async fn persona_sentence(
+        msg: &Message,
+        guild_id: GuildId,
+        data: &Data,
+        seeds: &[String],
+    ) -> Result<Option<String>, Error> {
+        let personality = match data.db.fetch_reply_persona(guild_id.0).await? {
+            ReplyPersona::Member(member_id) => Personality::Members(vec![(member_id, 1.0)]),
+            ReplyPersona::Channel => Personality::Channel(msg.channel_id.0),
+            ReplyPersona::Server => Personality::Server(guild_id.0),
+        };
+        match data
+            .model
+            .generate_reply(&personality, DEFAULT_TEMPERATURE, seeds)
+            .await
+        {
+            Ok(sentence) => Ok(Some(sentence)),
+            // Nothing to say yet
+            Err(MarkovFetchResultError::NotEnoughWords(_, _))
+            | Err(MarkovFetchResultError::NotOriginal) => Ok(None),
+            Err(why) => Err(why.into()),
+        }
+    }
 
-#[group]
-#[owners_only]
-#[commands(stop)]
-struct Owner;
+    /// Routes the gateway events poise passes through to the handler above.
+    async fn dispatch(
+        ctx: &Context,
+        event: &poise::Event<'_>,
+        framework: poise::FrameworkContext<'_, Data, Error>,
+        data: &Data,
+    ) -> Result<(), Error> {
+        match event {
+            poise::Event::Ready { data_about_bot } => Handler::ready(ctx, data_about_bot).await,
+            poise::Event::Resume { event } => Handler::resume(ctx, event).await,
+            poise::Event::Message { new_message } => {
+                Handler::message(ctx, new_message, framework.bot_id, data).await
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -80,39 +195,77 @@ async fn main() {
     let database_url =
         env::var("DATABASE_URL").expect("Should have DATABASE_URL present in .env file.");
 
-    let http = Http::new(&token);
-
-    // We will fetch your bot's owners and id
-    let (owners, _bot_id) = match http.get_current_application_info().await {
-        Ok(info) => {
-            let mut owners = HashSet::new();
-            owners.insert(info.owner.id);
+    // Connect to the database and bring its schema up to date before anything can use it
+    let db_int = DbInterface::new(&database_url).await;
+    info!("Connected to the database");
 
-            (owners, info.id)
-        }
-        Err(why) => panic!("Could not access application info: {:?}", why),
+    // Here we list every command. Owners are fetched from the application info by the framework.
+    let options = poise::FrameworkOptions {
+        commands: vec![
+            general::mimic(),
+            general::mashup(),
+            general::mimicchannel(),
+            general::mimicserver(),
+            general::stats(),
+            general::trackmember(),
+            general::untrackmember(),
+            general::listtrackedmembers(),
+            general::trackchannel(),
+            general::untrackchannel(),
+            general::chimein(),
+            general::stopchimein(),
+            general::listtrackedchannels(),
+            general::learn(),
+            general::markovorder(),
+            general::originality(),
+            general::replypersona(),
+            game::whosaidit(),
+            game::leaderboard(),
+            game::resetleaderboard(),
+            owner::stop(),
+        ],
+        // Generated text is written by the bot but repeats what members said, so nothing the bot sends may ping anyone
+        allowed_mentions: Some({
+            let mut allowed_mentions = serenity::builder::CreateAllowedMentions::default();
+            allowed_mentions.empty_parse().replied_user(false);
+            allowed_mentions
+        }),
+        prefix_options: poise::PrefixFrameworkOptions {
+            prefix: Some(PREFIX.into()),
+            // Mentions start a conversation instead
+            mention_as_prefix: false,
+            ..Default::default()
+        },
+        on_error: |error| Box::pin(on_error(error)),
+        event_handler: |ctx, event, framework, data| {
+            Box::pin(Handler::dispatch(ctx, event, framework, data))
+        },
+        ..Default::default()
     };
 
-    // Create the framework
-    let framework = StandardFramework::new()
-        .configure(|c| c.owners(owners).prefix("triple ").ignore_bots(true))
-        .group(&GENERAL_GROUP)
-        .group(&OWNER_GROUP);
-
-    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
-    let mut client = Client::builder(&token, intents)
-        .framework(framework)
-        .event_handler(Handler)
+    let intents =
+        GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let framework = poise::Framework::builder()
+        .token(token)
+        .intents(intents)
+        .options(options)
+        .setup(|ctx, _ready, framework| {
+            Box::pin(async move {
+                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                let db_int = Arc::new(db_int);
+                Ok(Data {
+                    db: db_int.clone(),
+                    model: db_int,
+                    chime: Mutex::new(HashMap::new()),
+                    games: Mutex::new(HashSet::new()),
+                })
+            })
+        })
+        .build()
         .await
         .expect("Err creating client");
 
-    // This block is here to ensure that the lock is released from data after we insert the shard manager
-    {
-        let mut data = client.data.write().await;
-        data.insert::<ShardManagerContainer>(client.shard_manager.clone());
-    }
-
-    let shard_manager = client.shard_manager.clone();
+    let shard_manager = framework.shard_manager().clone();
 
     // This spawns a kill switch thread to shut down the bot using CTRL+C
     tokio::spawn(async move {
@@ -123,7 +276,7 @@ async fn main() {
     });
 
     // This starts the bot, and if an error occurs it logs it to logs.
-    if let Err(why) = client.start().await {
+    if let Err(why) = framework.start().await {
         error!("Client error: {:?}", why);
     }
 }